@@ -4,10 +4,13 @@ use serde::{Deserialize, Serialize};
 use std::cmp::max;
 
 use crate::{
+    cheat::Cheats,
     config, context,
+    debugger::Debugger,
     io::Io,
     mbc::{Mbc, MbcTrait},
     ppu,
+    trace::{TraceEntry, Tracer},
     util::{pack, trait_alias},
 };
 
@@ -33,6 +36,12 @@ pub struct Bus {
     reg_ff72: u8,
     reg_ff73: u8,
     reg_ff75: u8,
+    #[serde(skip)]
+    cheats: Cheats,
+    #[serde(skip)]
+    debugger: Debugger,
+    #[serde(skip)]
+    tracer: Option<Tracer>,
 }
 
 trait_alias!(pub trait Context =
@@ -99,6 +108,9 @@ impl Bus {
             reg_ff72: 0,
             reg_ff73: 0,
             reg_ff75: 0,
+            cheats: Cheats::default(),
+            debugger: Debugger::default(),
+            tracer: None,
         }
     }
 
@@ -115,9 +127,32 @@ impl Bus {
         }
     }
 
+    /// Applies GameShark codes, called once per frame when VBlank starts
+    pub fn apply_game_shark(&mut self, ctx: &mut impl Context) {
+        let codes = self.cheats.game_shark().collect::<Vec<_>>();
+        for (addr, data, wram_bank) in codes {
+            match wram_bank {
+                // Select the WRAM bank only while writing, like the real device does
+                Some(bank) if (0xD000..=0xDFFF).contains(&addr) && self.ram.len() > 0x2000 => {
+                    let prev = self.ram_bank;
+                    self.ram_bank = max(bank, 1);
+                    self.write(ctx, addr, data);
+                    self.ram_bank = prev;
+                }
+                _ => self.write(ctx, addr, data),
+            }
+        }
+    }
+
+    // Game Genie codes intercept reads of cartridge ROM
+    fn read_rom(&mut self, ctx: &mut impl Context, addr: u16) -> u8 {
+        let data = self.mbc.read(ctx, addr);
+        self.cheats.patch_rom(addr, data)
+    }
+
     fn read_(&mut self, ctx: &mut impl Context, addr: u16) -> u8 {
         match addr {
-            0x0100..=0x01FF => self.mbc.read(ctx, addr),
+            0x0100..=0x01FF => self.read_rom(ctx, addr),
             0x0000..=0x08FF => {
                 let is_boot_rom = self.map_boot_rom
                     && self
@@ -131,10 +166,10 @@ impl Bus {
                         !0
                     }
                 } else {
-                    self.mbc.read(ctx, addr)
+                    self.read_rom(ctx, addr)
                 }
             }
-            0x0900..=0x7FFF => self.mbc.read(ctx, addr),
+            0x0900..=0x7FFF => self.read_rom(ctx, addr),
             0x8000..=0x9FFF => {
                 ctx.vram()[((addr & 0x1FFF) | (self.vram_bank as u16 * 0x2000)) as usize]
             }
@@ -389,6 +424,78 @@ impl Bus {
         &mut self.mbc
     }
 
+    /// Work RAM of all banks
+    pub fn wram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    pub fn hram(&self) -> &[u8] {
+        &self.hiram
+    }
+
+    pub fn cheats(&self) -> &Cheats {
+        &self.cheats
+    }
+
+    pub fn cheats_mut(&mut self) -> &mut Cheats {
+        &mut self.cheats
+    }
+
+    pub fn debugger(&self) -> &Debugger {
+        &self.debugger
+    }
+
+    pub fn debugger_mut(&mut self) -> &mut Debugger {
+        &mut self.debugger
+    }
+
+    /// Bank mapped at `addr`, for ROM, VRAM and WRAM
+    pub fn bank(&self, addr: u16) -> u16 {
+        match addr {
+            0x0000..=0x7FFF => {
+                let is_boot_rom = self.map_boot_rom
+                    && !(0x0100..=0x01FF).contains(&addr)
+                    && self
+                        .boot_rom
+                        .as_ref()
+                        .is_some_and(|r| r.len() > addr as usize);
+                if is_boot_rom {
+                    0
+                } else {
+                    self.mbc.rom_bank(addr)
+                }
+            }
+            0x8000..=0x9FFF => self.vram_bank as u16,
+            0xD000..=0xDFFF => self.ram_bank as u16,
+            _ => 0,
+        }
+    }
+
+    pub fn check_breakpoint(&mut self, pc: u16) -> bool {
+        if !self.debugger.is_enabled() {
+            return false;
+        }
+        let bank = self.bank(pc);
+        self.debugger.check_breakpoint(bank, pc)
+    }
+
+    pub fn tracer(&self) -> Option<&Tracer> {
+        self.tracer.as_ref()
+    }
+
+    pub fn tracer_mut(&mut self) -> &mut Option<Tracer> {
+        &mut self.tracer
+    }
+
+    pub fn trace(&mut self, entry: &TraceEntry) {
+        if let Some(tracer) = &mut self.tracer {
+            if let Err(err) = tracer.trace(entry) {
+                warn!("Failed to write trace log, tracing disabled: {err}");
+                self.tracer = None;
+            }
+        }
+    }
+
     pub fn boot_rom(&self) -> &Option<Vec<u8>> {
         &self.boot_rom
     }
@@ -408,6 +515,9 @@ impl Bus {
         self.process_dma(ctx);
         self.process_hdma(ctx);
 
+        let dots = if self.current_speed == 0 { 4 } else { 2 };
+        self.mbc.tick(ctx, dots);
+
         if self.switch_delay > 0 {
             self.switch_delay -= 1;
             if self.switch_delay == 0 {
@@ -0,0 +1,222 @@
+//! Cheat codes
+
+#[derive(thiserror::Error, Debug)]
+pub enum CheatError {
+    #[error("Invalid cheat code: {0}")]
+    InvalidCode(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct Cheat {
+    /// Code as given by the user
+    pub code: String,
+    pub enabled: bool,
+    pub kind: CheatKind,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CheatKind {
+    /// Replaces a byte read from cartridge ROM, only when the original byte
+    /// equals `compare` if it is given
+    GameGenie {
+        address: u16,
+        value: u8,
+        compare: Option<u8>,
+    },
+    /// Writes a byte to RAM once per frame at VBlank, to CGB WRAM bank
+    /// `wram_bank` if it is given
+    GameShark {
+        address: u16,
+        value: u8,
+        wram_bank: Option<u8>,
+    },
+}
+
+impl Cheat {
+    /// Parses a cheat code
+    ///
+    /// - Game Genie: `ABC-DEF-GHI`, or `ABC-DEF` without a compare value
+    /// - GameShark: `01VVAAAA` with the address in little endian,
+    ///   `9XVVAAAA` writes to WRAM bank `X` on CGB
+    pub fn parse(code: &str) -> Result<Self, CheatError> {
+        let invalid = || CheatError::InvalidCode(code.to_string());
+
+        let digits = code
+            .trim()
+            .split('-')
+            .collect::<String>()
+            .chars()
+            .map(|c| c.to_digit(16).map(|d| d as u8))
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(invalid)?;
+
+        let kind = match digits.len() {
+            6 | 9 => {
+                let d = &digits;
+                let value = d[0] << 4 | d[1];
+                let address = ((d[5] ^ 0xF) as u16) << 12
+                    | (d[2] as u16) << 8
+                    | (d[3] as u16) << 4
+                    | d[4] as u16;
+                // The 8th digit is a check digit and is not used
+                let compare = (d.len() == 9).then(|| (d[6] << 4 | d[8]).rotate_right(2) ^ 0xBA);
+                if address >= 0x8000 {
+                    Err(invalid())?
+                }
+                CheatKind::GameGenie {
+                    address,
+                    value,
+                    compare,
+                }
+            }
+            8 => {
+                let b = |i: usize| digits[i] << 4 | digits[i + 1];
+                let value = b(2);
+                let address = u16::from_le_bytes([b(4), b(6)]);
+                let wram_bank = match b(0) {
+                    0x00..=0x0F => None,
+                    0x90..=0x97 => Some(b(0) & 7),
+                    _ => Err(invalid())?,
+                };
+                if address < 0x8000 {
+                    Err(invalid())?
+                }
+                CheatKind::GameShark {
+                    address,
+                    value,
+                    wram_bank,
+                }
+            }
+            _ => Err(invalid())?,
+        };
+
+        Ok(Self {
+            code: code.trim().to_string(),
+            enabled: true,
+            kind,
+        })
+    }
+}
+
+/// Cheat codes applied to the running game
+///
+/// This is not serialized, so cheats never end up in save states.
+#[derive(Default)]
+pub struct Cheats {
+    cheats: Vec<Cheat>,
+}
+
+impl Cheats {
+    pub fn list(&self) -> &[Cheat] {
+        &self.cheats
+    }
+
+    pub fn add(&mut self, code: &str) -> Result<usize, CheatError> {
+        self.cheats.push(Cheat::parse(code)?);
+        Ok(self.cheats.len() - 1)
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<Cheat> {
+        (index < self.cheats.len()).then(|| self.cheats.remove(index))
+    }
+
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) {
+        if let Some(cheat) = self.cheats.get_mut(index) {
+            cheat.enabled = enabled;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.cheats.clear();
+    }
+
+    /// Applies Game Genie codes to a byte read from cartridge ROM
+    pub fn patch_rom(&self, addr: u16, data: u8) -> u8 {
+        for cheat in self.cheats.iter().filter(|c| c.enabled) {
+            if let CheatKind::GameGenie {
+                address,
+                value,
+                compare,
+            } = cheat.kind
+            {
+                if address == addr && compare.is_none_or(|c| c == data) {
+                    return value;
+                }
+            }
+        }
+        data
+    }
+
+    /// Enabled GameShark codes as `(address, value, wram_bank)`
+    pub fn game_shark(&self) -> impl Iterator<Item = (u16, u8, Option<u8>)> + '_ {
+        self.cheats
+            .iter()
+            .filter(|c| c.enabled)
+            .filter_map(|c| match c.kind {
+                CheatKind::GameShark {
+                    address,
+                    value,
+                    wram_bank,
+                } => Some((address, value, wram_bank)),
+                _ => None,
+            })
+    }
+}
+
+#[test]
+fn test_game_genie() {
+    let cheat = Cheat::parse("3C1-23B-EFA").unwrap();
+    assert_eq!(
+        cheat.kind,
+        CheatKind::GameGenie {
+            address: 0x4123,
+            value: 0x3C,
+            compare: Some(0x00),
+        }
+    );
+    let cheat = Cheat::parse("00A17B").unwrap();
+    assert_eq!(
+        cheat.kind,
+        CheatKind::GameGenie {
+            address: 0x4A17,
+            value: 0x00,
+            compare: None,
+        }
+    );
+    assert!(Cheat::parse("3C1-230-EFA").is_err());
+    assert!(Cheat::parse("3C1-23B-EF").is_err());
+    assert!(Cheat::parse("XYZ-23B-EFA").is_err());
+
+    let mut cheats = Cheats::default();
+    cheats.add("3C1-23B-EFA").unwrap();
+    assert_eq!(cheats.patch_rom(0x4123, 0x00), 0x3C);
+    // Compare value does not match the data in the current bank
+    assert_eq!(cheats.patch_rom(0x4123, 0x01), 0x01);
+    assert_eq!(cheats.patch_rom(0x4124, 0x00), 0x00);
+    cheats.set_enabled(0, false);
+    assert_eq!(cheats.patch_rom(0x4123, 0x00), 0x00);
+}
+
+#[test]
+fn test_game_shark() {
+    let cheat = Cheat::parse("01FF34C2").unwrap();
+    assert_eq!(
+        cheat.kind,
+        CheatKind::GameShark {
+            address: 0xC234,
+            value: 0xFF,
+            wram_bank: None,
+        }
+    );
+    let cheat = Cheat::parse("93630DD0").unwrap();
+    assert_eq!(
+        cheat.kind,
+        CheatKind::GameShark {
+            address: 0xD00D,
+            value: 0x63,
+            wram_bank: Some(3),
+        }
+    );
+    assert!(Cheat::parse("01FF3412").is_err());
+    assert!(Cheat::parse("A1FF34C2").is_err());
+}
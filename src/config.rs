@@ -2,6 +2,8 @@ use meru_interface::{Color, File};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use crate::{dat::GameDatabase, patch::apply_patch};
+
 #[derive(Clone, JsonSchema, Serialize, Deserialize)]
 pub struct Config {
     /// Hardware model
@@ -16,6 +18,18 @@ pub struct Config {
     pub custom_palette: Palette,
     /// Color Correction
     pub color_correction: bool,
+    /// MBC1 multicart (MBC1M) wiring
+    pub mbc1_multicart: Mbc1Multicart,
+    /// Advance the cartridge clock by the host time elapsed since the battery save was written
+    pub sync_rtc_with_host: bool,
+    /// Handling of backup data that does not fit the cartridge
+    pub mismatched_backup: MismatchedBackup,
+    /// Load ROM images with a wrong size or invalid header fields
+    pub tolerant_rom_loading: bool,
+    /// IPS, UPS or BPS patches applied to the ROM in order
+    pub patches: Vec<File>,
+    /// No-Intro DAT file to identify games
+    pub game_database: Option<File>,
 }
 
 impl Default for Config {
@@ -27,6 +41,12 @@ impl Default for Config {
             palette: PaletteSelect::Pocket,
             custom_palette: PALETTE_GRAYSCALE,
             color_correction: true,
+            mbc1_multicart: Mbc1Multicart::Auto,
+            sync_rtc_with_host: false,
+            mismatched_backup: MismatchedBackup::Error,
+            tolerant_rom_loading: false,
+            patches: vec![],
+            game_database: None,
         }
     }
 }
@@ -63,6 +83,26 @@ pub enum BootRom {
     Custom,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug, JsonSchema, Serialize, Deserialize)]
+pub enum Mbc1Multicart {
+    /// Detect from the ROM image
+    Auto,
+    /// Always use multicart wiring for MBC1 cartridges
+    Force,
+    /// Never use multicart wiring
+    Disable,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, JsonSchema, Serialize, Deserialize)]
+pub enum MismatchedBackup {
+    /// Fail to load the ROM
+    Error,
+    /// Truncate or pad with zeros to the size of the cartridge RAM
+    Resize,
+    /// Discard the backup and start with empty RAM
+    Ignore,
+}
+
 #[derive(Clone, Default, JsonSchema, Serialize, Deserialize)]
 pub struct CustomBootRoms {
     /// Game Boy
@@ -142,6 +182,23 @@ impl Config {
         })
     }
 
+    /// Applies the patches to ROM data
+    pub fn patch_rom(&self, data: &[u8]) -> Result<Vec<u8>, crate::gameboy::Error> {
+        let mut ret = data.to_vec();
+        for patch in &self.patches {
+            ret = apply_patch(&ret, &patch.data()?)?;
+        }
+        Ok(ret)
+    }
+
+    pub fn game_database(&self) -> Result<Option<GameDatabase>, std::io::Error> {
+        let Some(file) = &self.game_database else {
+            return Ok(None);
+        };
+        let dat = file.data()?;
+        Ok(Some(GameDatabase::from_dat(&String::from_utf8_lossy(&dat))))
+    }
+
     pub fn palette(&self) -> &Palette {
         self.palette.get_palette().unwrap_or(&self.custom_palette)
     }
@@ -1,8 +1,19 @@
 use ambassador::{delegatable_trait, Delegate};
 use meru_interface::Color;
 use serde::{Deserialize, Serialize};
+use std::cmp::min;
 
-use crate::{apu, config, gameboy::Error, mbc::create_mbc, ppu, rom, util::to_si_bytesize};
+use log::warn;
+
+use crate::{
+    apu,
+    config::{self, MismatchedBackup},
+    gameboy::Error,
+    mbc::{create_mbc, internal_ram_size},
+    ppu, rom,
+    trace::TraceEntry,
+    util::to_si_bytesize,
+};
 
 #[delegatable_trait]
 pub trait Bus {
@@ -11,6 +22,10 @@ pub trait Bus {
     fn read(&mut self, addr: u16) -> u8;
     fn read_immutable(&mut self, addr: u16) -> Option<u8>;
     fn write(&mut self, addr: u16, data: u8);
+    fn check_breakpoint(&mut self, pc: u16) -> bool;
+    fn bank(&self, addr: u16) -> u16;
+    fn is_tracing(&self) -> bool;
+    fn trace(&mut self, entry: &TraceEntry);
 }
 
 #[delegatable_trait]
@@ -98,24 +113,76 @@ pub trait InterruptFlag {
     }
 }
 
+fn fit_backup(
+    mut data: Vec<u8>,
+    size: usize,
+    mismatched_backup: MismatchedBackup,
+) -> Result<Option<Vec<u8>>, Error> {
+    if data.len() == size {
+        return Ok(Some(data));
+    }
+    let (expected, actual) = (size, data.len());
+    match mismatched_backup {
+        MismatchedBackup::Error => Err(Error::BackupSizeMismatch { expected, actual })?,
+        MismatchedBackup::Resize => {
+            warn!(
+                "Backup size mismatch: {} != {}. Resize backup data",
+                to_si_bytesize(actual as _),
+                to_si_bytesize(expected as _)
+            );
+            data.resize(expected, 0);
+            Ok(Some(data))
+        }
+        MismatchedBackup::Ignore => {
+            warn!(
+                "Backup size mismatch: {} != {}. Ignore backup data",
+                to_si_bytesize(actual as _),
+                to_si_bytesize(expected as _)
+            );
+            Ok(None)
+        }
+    }
+}
+
 impl Context {
     pub fn new(
         model: crate::config::Model,
         rom: crate::rom::Rom,
         boot_rom: &Option<Vec<u8>>,
         backup_ram: Option<Vec<u8>>,
+        mismatched_backup: MismatchedBackup,
         dmg_palette: &[Color; 4],
     ) -> Result<Self, Error> {
         let io = crate::io::Io::new();
 
-        let mut backup_ram = backup_ram;
-        let internal_ram = if rom.cartridge_type.has_internal_ram() {
-            let mut dmy = None;
-            std::mem::swap(&mut dmy, &mut backup_ram);
-            dmy
-        } else {
-            None
+        let (backup_ram, internal_ram) = match backup_ram {
+            Some(_) if !rom.cartridge_type.has_battery => match mismatched_backup {
+                MismatchedBackup::Error => Err(Error::BackupNotSupported)?,
+                _ => {
+                    warn!("Cartridge has no battery backed memory. Ignore backup data");
+                    (None, None)
+                }
+            },
+            Some(mut ram) => {
+                let external_ram_size = rom.ram_size as usize;
+                // Backup data of mapper's internal memory follows external RAM
+                let internal_ram = if rom.cartridge_type.has_internal_ram() {
+                    Some(ram.split_off(min(external_ram_size, ram.len())))
+                } else {
+                    None
+                };
+                let internal_ram = match (internal_ram, internal_ram_size(&rom)) {
+                    (Some(data), Some(size)) => fit_backup(data, size, mismatched_backup)?,
+                    (data, _) => data,
+                };
+                let ram = fit_backup(ram, external_ram_size, mismatched_backup)?;
+                (ram, internal_ram)
+            }
+            None => (None, None),
         };
+
+        let mut rom = rom;
+        rom.mirror_banks();
         let mbc = create_mbc(&rom, internal_ram)?;
         let bus = crate::bus::Bus::new(model, mbc, boot_rom, io);
         let vram_size = if model.is_cgb() { 0x4000 } else { 0x2000 };
@@ -125,21 +192,7 @@ impl Context {
             RunningMode::Dmg
         };
 
-        let external_ram = if let Some(ram) = backup_ram {
-            if !rom.cartridge_type.has_battery {
-                panic!("Trying to load backup RAM even cartridge has no battery backup RAM");
-            }
-            if ram.len() != rom.ram_size as usize {
-                panic!(
-                    "Loading backup RAM size does not match ROM's info: {} != {}",
-                    to_si_bytesize(ram.len() as _),
-                    to_si_bytesize(rom.ram_size as _)
-                );
-            }
-            ram
-        } else {
-            vec![0; rom.ram_size as usize]
-        };
+        let external_ram = backup_ram.unwrap_or_else(|| vec![0; rom.ram_size as usize]);
 
         Ok(Self {
             cpu: crate::cpu::Cpu::new(),
@@ -223,7 +276,9 @@ impl Bus for InnerContext0 {
     }
 
     fn read(&mut self, addr: u16) -> u8 {
-        self.bus.read(&mut self.inner, addr)
+        let data = self.bus.read(&mut self.inner, addr);
+        self.bus.debugger_mut().watch_read(addr, data);
+        data
     }
 
     fn read_immutable(&mut self, addr: u16) -> Option<u8> {
@@ -231,8 +286,25 @@ impl Bus for InnerContext0 {
     }
 
     fn write(&mut self, addr: u16, data: u8) {
+        self.bus.debugger_mut().watch_write(addr, data);
         self.bus.write(&mut self.inner, addr, data)
     }
+
+    fn check_breakpoint(&mut self, pc: u16) -> bool {
+        self.bus.check_breakpoint(pc)
+    }
+
+    fn bank(&self, addr: u16) -> u16 {
+        self.bus.bank(addr)
+    }
+
+    fn is_tracing(&self) -> bool {
+        self.bus.tracer().is_some()
+    }
+
+    fn trace(&mut self, entry: &TraceEntry) {
+        self.bus.trace(entry)
+    }
 }
 
 #[derive(Serialize, Deserialize, Delegate)]
@@ -4,6 +4,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
     context,
+    trace::TraceEntry,
     util::{trait_alias, ConstEval},
 };
 
@@ -15,6 +16,9 @@ pub struct Cpu {
     reg: Register,
     cycle: u64,
     period: u64,
+    // Number of instructions and interrupt dispatches started
+    #[serde(skip)]
+    instruction_count: u64,
 }
 
 trait_alias!(pub trait Context = context::Bus + context::InterruptFlag);
@@ -206,6 +210,27 @@ impl Cpu {
         &mut self.reg
     }
 
+    /// Returns true if the last instruction has cycles left to be consumed by subsequent `step`s
+    /// Machine cycles elapsed since power on
+    ///
+    /// While busy, the running instruction has already been executed ahead of this.
+    pub fn elapsed_cycles(&self) -> u64 {
+        self.period
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.cycle > self.period
+    }
+
+    pub fn is_halting(&self) -> bool {
+        self.halting
+    }
+
+    /// Number of instructions and interrupt dispatches started, not saved in save states
+    pub fn instruction_count(&self) -> u64 {
+        self.instruction_count
+    }
+
     pub fn step(&mut self, ctx: &mut impl Context) {
         self.period += 1;
         while self.cycle < self.period {
@@ -228,6 +253,18 @@ impl Cpu {
             }
 
             let pc = self.reg.pc;
+            // A pending interrupt is dispatched instead of the instruction at `pc`,
+            // so the breakpoint is checked on the handler in the next iteration
+            let interrupting =
+                self.prev_interrupt_enable && ctx.interrupt_flag() & ctx.interrupt_enable() != 0;
+            if !interrupting && ctx.check_breakpoint(pc) {
+                // The instruction is executed on the next step
+                self.period = self.cycle;
+                return;
+            }
+            self.instruction_count += 1;
+            // Captured before the opcode fetch consumes a cycle
+            let trace_start = ctx.is_tracing().then(|| (self.cycle, read_pcmem(ctx, pc)));
             let opc = self.fetch(ctx);
             if self.process_interrupt(ctx, pc) {
                 continue;
@@ -236,6 +273,9 @@ impl Cpu {
             if log_enabled!(Level::Trace) {
                 self.trace(ctx, pc, opc);
             }
+            if let Some((cycle, pcmem)) = trace_start {
+                self.trace_to_sink(ctx, pc, cycle, pcmem);
+            }
             self.exec_instr(ctx, opc);
         }
     }
@@ -931,17 +971,19 @@ impl Cpu {
 
 impl Cpu {
     fn trace(&mut self, ctx: &mut impl Context, pc: u16, opc: u8) {
-        let opr1 = ctx.read_immutable(pc.wrapping_add(1));
-        let opr2 = ctx.read_immutable(pc.wrapping_add(2));
-
-        let (asm, op_len) = disasm(pc, opc, opr1, opr2);
-
-        let tos = |mb: Option<u8>| mb.map_or("??".to_string(), |x| format!("{x:02X}"));
-        let bytes = match op_len {
-            1 => format!("{:02X}", opc),
-            2 => format!("{:02X} {}", opc, tos(opr1)),
-            3 => format!("{:02X} {} {}", opc, tos(opr1), tos(opr2)),
-            _ => unreachable!(),
+        let instr = disassemble_with(pc, |addr| ctx.read_immutable(addr));
+
+        let (bytes, asm) = match &instr {
+            Some(instr) => (
+                instr
+                    .bytes
+                    .iter()
+                    .map(|b| format!("{b:02X}"))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                instr.to_string(),
+            ),
+            None => (format!("{opc:02X}"), "???".to_string()),
         };
 
         use crate::consts::*;
@@ -970,6 +1012,28 @@ impl Cpu {
             lx = self.cycle % CPU_CLOCK_PER_LINE,
         );
     }
+
+    fn trace_to_sink(&mut self, ctx: &mut impl Context, pc: u16, cycle: u64, pcmem: [u8; 4]) {
+        let entry = TraceEntry {
+            reg: &self.reg,
+            pc,
+            bank: ctx.bank(pc),
+            pcmem,
+            ime: self.interrupt_master_enable,
+            cycle,
+        };
+        ctx.trace(&entry);
+    }
+}
+
+fn read_pcmem(ctx: &mut impl Context, pc: u16) -> [u8; 4] {
+    let mut ret = [0xFF; 4];
+    for (i, b) in ret.iter_mut().enumerate() {
+        if let Some(data) = ctx.read_immutable(pc.wrapping_add(i as u16)) {
+            *b = data;
+        }
+    }
+    ret
 }
 
 #[rustfmt::skip]
@@ -997,85 +1061,197 @@ const HWREG_NAME: &[(u16, &str)] = &[
     (0xFFFF, "IE"),
 ];
 
-fn hwreg_name(addr: u8) -> Option<&'static str> {
-    HWREG_NAME
-        .iter()
-        .find(|r| addr as u16 | 0xFF00 == r.0)
-        .map(|r| r.1)
+/// Name of the hardware register at `addr`
+pub fn hwreg_name(addr: u16) -> Option<&'static str> {
+    HWREG_NAME.iter().find(|r| addr == r.0).map(|r| r.1)
+}
+
+/// Disassembled instruction
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Instruction {
+    pub address: u16,
+    /// Opcode and operand bytes
+    pub bytes: Vec<u8>,
+    pub mnemonic: &'static str,
+    pub operands: Vec<Operand>,
+    /// Machine cycles, when the branch is not taken for conditional branches
+    pub cycles: u8,
+    /// Machine cycles when the conditional branch is taken
+    pub branch_cycles: Option<u8>,
+    /// Destination of jumps, calls and restarts to a fixed address
+    pub branch_target: Option<u16>,
 }
 
-fn disasm(pc: u16, opc: u8, opr1: Option<u8>, opr2: Option<u8>) -> (String, usize) {
-    let opc = opc;
-    let opr1 = opr1;
-    let opr2 = opr2;
-    let mut bytes = 1;
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Operand {
+    Register(&'static str),
+    Condition(&'static str),
+    /// Memory pointed by a register, `HL+` and `HL-` for auto increment and decrement
+    Indirect(&'static str),
+    Imm8(u8),
+    Imm16(u16),
+    /// `SP` with a signed offset
+    SpOffset(i8),
+    /// Relative jump, resolved to the destination address
+    Relative(u16),
+    /// Memory at $FF00-$FFFF addressed by an 8-bit offset
+    High {
+        address: u16,
+        name: Option<&'static str>,
+    },
+    /// Memory at a 16-bit address
+    Absolute {
+        address: u16,
+        name: Option<&'static str>,
+    },
+    /// Bit number of `BIT`, `RES` and `SET`
+    Bit(u8),
+    /// Destination of `RST`
+    Vector(u8),
+}
+
+impl Instruction {
+    pub fn length(&self) -> usize {
+        self.bytes.len()
+    }
+}
+
+impl std::fmt::Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.mnemonic)?;
+        for (i, opr) in self.operands.iter().enumerate() {
+            write!(f, "{}{opr}", if i == 0 { " " } else { ", " })?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for Operand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Operand::Register(name) | Operand::Condition(name) => write!(f, "{name}"),
+            Operand::Indirect(name) => write!(f, "({name})"),
+            Operand::Imm8(n) => write!(f, "${n:02X}"),
+            Operand::Imm16(nn) | Operand::Relative(nn) => write!(f, "${nn:04X}"),
+            Operand::SpOffset(n) => write!(f, "SP{n:+}"),
+            Operand::High { address, name } => match name {
+                Some(name) => write!(f, "(<{name}=${:02X})", address as u8),
+                None => write!(f, "(${:02X})", address as u8),
+            },
+            Operand::Absolute { address, name } => match name {
+                Some(name) => write!(f, "(<{name}=${address:04X})"),
+                None => write!(f, "(${address:04X})"),
+            },
+            Operand::Bit(n) => write!(f, "{n}"),
+            Operand::Vector(n) => write!(f, "{n:02X}H"),
+        }
+    }
+}
+
+#[rustfmt::skip]
+const CYCLES: [u8; 256] = [
+    1, 3, 2, 2, 1, 1, 2, 1, 5, 2, 2, 2, 1, 1, 2, 1,
+    1, 3, 2, 2, 1, 1, 2, 1, 3, 2, 2, 2, 1, 1, 2, 1,
+    2, 3, 2, 2, 1, 1, 2, 1, 2, 2, 2, 2, 1, 1, 2, 1,
+    2, 3, 2, 2, 3, 3, 3, 1, 2, 2, 2, 2, 1, 1, 2, 1,
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+    2, 2, 2, 2, 2, 2, 1, 2, 1, 1, 1, 1, 1, 1, 2, 1,
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+    2, 3, 3, 4, 3, 4, 2, 4, 2, 4, 3, 0, 3, 6, 2, 4,
+    2, 3, 3, 0, 3, 4, 2, 4, 2, 4, 3, 0, 3, 0, 2, 4,
+    3, 3, 2, 0, 0, 4, 2, 4, 4, 1, 4, 0, 0, 0, 2, 4,
+    3, 3, 2, 1, 0, 4, 2, 4, 3, 2, 4, 1, 0, 0, 2, 4,
+];
+
+/// Disassembles the instruction at the beginning of `bytes`, placed at `address`
+///
+/// Returns `None` if `bytes` ends in the middle of the instruction.
+pub fn disassemble(address: u16, bytes: &[u8]) -> Option<Instruction> {
+    disassemble_with(address, |addr| {
+        bytes.get(addr.wrapping_sub(address) as usize).copied()
+    })
+}
+
+/// Disassembles the instruction at `address`, reading memory with `read`
+///
+/// Returns `None` if `read` fails for any byte of the instruction.
+pub fn disassemble_with(
+    address: u16,
+    mut read: impl FnMut(u16) -> Option<u8>,
+) -> Option<Instruction> {
+    let opc = read(address)?;
+    let mut bytes = vec![opc];
+
+    macro_rules! fetch {
+        () => {{
+            let b = read(address.wrapping_add(bytes.len() as u16))?;
+            bytes.push(b);
+            b
+        }};
+    }
 
     macro_rules! gen_opr {
         ((^HL)) => {
-            "(HL+)"
+            Operand::Indirect("HL+")
         };
         ((-HL)) => {
-            "(HL-)"
+            Operand::Indirect("HL-")
         };
 
-        (SPn) => {{
-            bytes += 1;
-            opr1.map_or_else(|| "SP+??".to_string(), |opr| format!("SP{:+}", opr as i8))
-        }};
+        (SPn) => {
+            Operand::SpOffset(fetch!() as i8)
+        };
 
-        (n) => {{
-            bytes += 1;
-            opr1.map_or_else(|| "$??".to_string(), |opr| format!("${opr:02X}"))
-        }};
+        (n) => {
+            Operand::Imm8(fetch!())
+        };
         ((n)) => {{
-            bytes += 1;
-            opr1.map_or_else(
-                || "($??)".to_string(),
-                |opr| {
-                    hwreg_name(opr).map_or_else(
-                        || format!("(${opr:02X})"),
-                        |name| format!("(<{name}=${opr:02X})"),
-                    )
-                },
-            )
+            let address = 0xFF00 | fetch!() as u16;
+            Operand::High {
+                address,
+                name: hwreg_name(address),
+            }
         }};
         (r8) => {{
-            bytes += 1;
-            opr1.map_or_else(
-                || "$????".to_string(),
-                |opr| format!("${:04X}", pc.wrapping_add(2).wrapping_add(opr as i8 as u16)),
-            )
+            let offset = fetch!() as i8;
+            Operand::Relative(address.wrapping_add(2).wrapping_add(offset as u16))
         }};
         (nn) => {{
-            bytes += 2;
-            opr1.and_then(|opr1| opr2.map(|opr2| format!("${:02X}{:02X}", opr2, opr1)))
-                .unwrap_or_else(|| "$????".to_string())
+            let lo = fetch!();
+            let hi = fetch!();
+            Operand::Imm16(u16::from_le_bytes([lo, hi]))
         }};
         ((nn)) => {{
-            bytes += 2;
-            opr1.and_then(|opr1| opr2.map(|opr2| format!("(${:02X}{:02X})", opr2, opr1)))
-                .unwrap_or_else(|| "($????)".to_string())
+            let lo = fetch!();
+            let hi = fetch!();
+            let address = u16::from_le_bytes([lo, hi]);
+            Operand::Absolute {
+                address,
+                name: hwreg_name(address),
+            }
         }};
 
         ($n:literal) => {
-            format!("{:02X}H", $n)
+            Operand::Vector($n)
         };
 
         ($opr:ident) => {
-            stringify!($opr)
+            Operand::Register(stringify!($opr))
         };
         (($opr:ident)) => {
-            stringify!(($opr))
+            Operand::Indirect(stringify!($opr))
         };
     }
 
     macro_rules! gen_disasm {
         ($($ix:expr => $mne:ident $opr:tt;)*) => {
             match opc {
-                $( ConstEval::<{$ix}>::VALUE => {
-                    let asm = gen_disasm!(@generate: $mne $opr);
-                    (asm, bytes)
-                })*
+                $( ConstEval::<{$ix}>::VALUE => gen_disasm!(@generate: $mne $opr), )*
             }
         };
 
@@ -1084,34 +1260,105 @@ fn disasm(pc: u16, opc: u8, opr1: Option<u8>, opr2: Option<u8>) -> (String, usiz
         };
 
         (@generate: $mne:ident []) => {
-            stringify!($mne).to_string()
+            (stringify!($mne), vec![])
         };
         (@generate: $mne:ident [$opr:tt]) => {
-            format!("{} {}", stringify!($mne), gen_opr!($opr))
-        };
-        (@generate: $mne:ident [$dst:tt, $src:tt]) => {
-            format!("{} {}, {}", stringify!($mne), gen_opr!($dst), gen_opr!($src))
+            (stringify!($mne), vec![gen_opr!($opr)])
         };
+        (@generate: $mne:ident [$dst:tt, $src:tt]) => {{
+            let dst = gen_opr!($dst);
+            let src = gen_opr!($src);
+            (stringify!($mne), vec![dst, src])
+        }};
     }
 
     macro_rules! gen_disasm_cb {
         ($($ix:expr => $mne:ident $opr:tt;)*) => {{
-            bytes += 1;
-            match opr1 {
-                $( Some(ConstEval::<{$ix}>::VALUE) => {
-                    gen_disasm_cb!(@generate: $mne $opr)
-                })*
-                None => format!("???"),
+            match fetch!() {
+                $( ConstEval::<{$ix}>::VALUE => gen_disasm_cb!(@generate: $mne $opr), )*
             }
         }};
 
         (@generate: $mne:ident [$opr:tt]) => {
-            format!("{} {}", stringify!($mne), gen_opr!($opr))
+            (stringify!($mne), vec![gen_opr!($opr)])
         };
         (@generate: $mne:ident [$n:literal, $opr:tt]) => {
-            format!("{} {}, {}", stringify!($mne), $n, gen_opr!($opr))
+            (stringify!($mne), vec![Operand::Bit($n), gen_opr!($opr)])
         };
     }
 
-    instructions!(gen_disasm)
+    let (mnemonic, mut operands): (&'static str, Vec<Operand>) = instructions!(gen_disasm);
+
+    let is_branch = matches!(mnemonic, "JR" | "JP" | "CALL" | "RET");
+    let conditional = is_branch && operands.len() == if mnemonic == "RET" { 1 } else { 2 };
+    if conditional {
+        if let Operand::Register(cond) = operands[0] {
+            operands[0] = Operand::Condition(cond);
+        }
+    }
+
+    let cycles = if opc == 0xCB {
+        match bytes[1] {
+            x if x & 7 != 6 => 2,
+            0x40..=0x7F => 3,
+            _ => 4,
+        }
+    } else {
+        CYCLES[opc as usize]
+    };
+    let branch_cycles = conditional.then(|| match mnemonic {
+        "JR" | "JP" => cycles + 1,
+        _ => cycles + 3,
+    });
+
+    let branch_target = match (mnemonic, operands.last()) {
+        ("JR" | "JP" | "CALL", Some(&Operand::Relative(addr) | &Operand::Imm16(addr))) => {
+            Some(addr)
+        }
+        ("RST", Some(&Operand::Vector(n))) => Some(n as u16),
+        _ => None,
+    };
+
+    Some(Instruction {
+        address,
+        bytes,
+        mnemonic,
+        operands,
+        cycles,
+        branch_cycles,
+        branch_target,
+    })
+}
+
+#[test]
+fn test_disassemble() {
+    let instr = disassemble(0x0150, &[0x20, 0xFE]).unwrap();
+    assert_eq!(instr.to_string(), "JR NZ, $0150");
+    assert_eq!(instr.operands[0], Operand::Condition("NZ"));
+    assert_eq!((instr.cycles, instr.branch_cycles), (2, Some(3)));
+    assert_eq!(instr.branch_target, Some(0x0150));
+
+    let instr = disassemble(0x0000, &[0xE0, 0x40]).unwrap();
+    assert_eq!(instr.to_string(), "LDH (<LCDC=$40), A");
+    assert_eq!(instr.length(), 2);
+
+    let instr = disassemble(0x0000, &[0xFA, 0x44, 0xFF]).unwrap();
+    assert_eq!(
+        instr.operands[1],
+        Operand::Absolute {
+            address: 0xFF44,
+            name: Some("LY")
+        }
+    );
+    assert_eq!(instr.cycles, 4);
+
+    let instr = disassemble(0x0000, &[0xCB, 0x7E]).unwrap();
+    assert_eq!(instr.to_string(), "BIT 7, (HL)");
+    assert_eq!(instr.cycles, 3);
+
+    assert_eq!(
+        disassemble(0x0000, &[0xFF]).unwrap().branch_target,
+        Some(0x38)
+    );
+    assert_eq!(disassemble(0x0000, &[0xCD, 0x00]), None);
 }
@@ -0,0 +1,177 @@
+//! Game identification with No-Intro DAT files (Logiqx XML format)
+
+use std::collections::HashMap;
+
+#[derive(Clone, Debug)]
+pub struct GameEntry {
+    /// Full name in the DAT file, such as "Tetris (World) (Rev 1)"
+    pub name: String,
+    /// Name without tags
+    pub title: String,
+    pub region: Option<String>,
+    pub revision: Option<String>,
+    /// Dump is marked as verified good
+    pub verified: bool,
+}
+
+#[derive(Default)]
+pub struct GameDatabase {
+    games: HashMap<[u8; 32], GameEntry>,
+}
+
+impl GameDatabase {
+    /// Parses a DAT file, entries without a SHA-256 hash are skipped
+    pub fn from_dat(dat: &str) -> Self {
+        let mut games = HashMap::new();
+
+        let mut rest = dat;
+        while let Some(start) = find_tag(rest, "game") {
+            let block = &rest[start..];
+            let end = block.find("</game>").map_or(block.len(), |i| i + 7);
+            rest = &block[end..];
+            let block = &block[..end];
+
+            let Some(name) = tag_attrs(block).and_then(|a| attr(a, "name")) else {
+                continue;
+            };
+
+            let mut roms = block;
+            while let Some(start) = find_tag(roms, "rom") {
+                roms = &roms[start..];
+                let attrs = tag_attrs(roms).unwrap_or_default();
+                roms = &roms[1..];
+
+                let Some(sha256) = attr(attrs, "sha256").and_then(|s| parse_hash(&s)) else {
+                    continue;
+                };
+                let verified = attr(attrs, "status").as_deref() == Some("verified");
+                games.insert(sha256, GameEntry::new(&name, verified));
+            }
+        }
+
+        Self { games }
+    }
+
+    pub fn lookup(&self, sha256: &[u8; 32]) -> Option<&GameEntry> {
+        self.games.get(sha256)
+    }
+
+    pub fn len(&self) -> usize {
+        self.games.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.games.is_empty()
+    }
+}
+
+impl GameEntry {
+    // No-Intro names are "Title (Region) (Languages) (Rev N) ..."
+    fn new(name: &str, verified: bool) -> Self {
+        let (title, tags) = name.split_once(" (").map_or((name, ""), |(t, r)| (t, r));
+        let tags = format!("({tags}");
+        let mut tags = tags
+            .split(['(', ')'])
+            .map(str::trim)
+            .filter(|s| !s.is_empty());
+
+        let region = tags.next().map(str::to_string);
+        let revision = tags
+            .find_map(|s| s.strip_prefix("Rev "))
+            .map(str::to_string);
+
+        Self {
+            name: name.to_string(),
+            title: title.to_string(),
+            region,
+            revision,
+            verified,
+        }
+    }
+}
+
+// Finds `<name` followed by whitespace or the end of the tag
+fn find_tag(s: &str, name: &str) -> Option<usize> {
+    let pat = format!("<{name}");
+    let mut pos = 0;
+    while let Some(i) = s[pos..].find(&pat) {
+        let i = pos + i;
+        match s[i + pat.len()..].chars().next() {
+            Some(c) if c.is_whitespace() || c == '>' || c == '/' => return Some(i),
+            _ => pos = i + pat.len(),
+        }
+    }
+    None
+}
+
+// Attribute part of the tag at the beginning of `s`
+fn tag_attrs(s: &str) -> Option<&str> {
+    let end = s.find('>')?;
+    let start = s.find(char::is_whitespace).filter(|&i| i < end)?;
+    Some(s[start..end].trim_end_matches('/'))
+}
+
+fn attr(attrs: &str, key: &str) -> Option<String> {
+    let mut rest = attrs;
+    loop {
+        rest = rest.trim_start();
+        let (k, r) = rest.split_once('=')?;
+        let r = r.trim_start().strip_prefix('"')?;
+        let (v, r) = r.split_once('"')?;
+        if k.trim() == key {
+            return Some(unescape(v));
+        }
+        rest = r;
+    }
+}
+
+fn unescape(s: &str) -> String {
+    s.replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+fn parse_hash(s: &str) -> Option<[u8; 32]> {
+    if s.len() != 64 {
+        return None;
+    }
+    let mut ret = [0; 32];
+    for (i, b) in ret.iter_mut().enumerate() {
+        *b = u8::from_str_radix(s.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    Some(ret)
+}
+
+#[test]
+fn test_from_dat() {
+    let dat = r#"<?xml version="1.0"?>
+<datafile>
+	<header><name>Nintendo - Game Boy</name></header>
+	<game name="Foo &amp; Bar (Japan) (Rev 1)">
+		<description>Foo &amp; Bar (Japan) (Rev 1)</description>
+		<rom name="Foo &amp; Bar (Japan) (Rev 1).gb" size="32768" crc="00000000" sha256="00112233445566778899AABBCCDDEEFF00112233445566778899aabbccddeeff" status="verified"/>
+	</game>
+	<game name="Baz (USA, Europe)">
+		<rom name="Baz (USA, Europe).gb" size="32768" sha256="ff00000000000000000000000000000000000000000000000000000000000000"/>
+	</game>
+</datafile>
+"#;
+    let db = GameDatabase::from_dat(dat);
+    assert_eq!(db.len(), 2);
+
+    let hash = parse_hash("00112233445566778899aabbccddeeff00112233445566778899aabbccddeeff");
+    let game = db.lookup(&hash.unwrap()).unwrap();
+    assert_eq!(game.title, "Foo & Bar");
+    assert_eq!(game.region.as_deref(), Some("Japan"));
+    assert_eq!(game.revision.as_deref(), Some("1"));
+    assert!(game.verified);
+
+    let mut hash = [0; 32];
+    hash[0] = 0xFF;
+    let game = db.lookup(&hash).unwrap();
+    assert_eq!(game.region.as_deref(), Some("USA, Europe"));
+    assert_eq!(game.revision, None);
+    assert!(!game.verified);
+}
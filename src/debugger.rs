@@ -0,0 +1,142 @@
+//! Breakpoints and watchpoints
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Breakpoint {
+    /// Bank of the address, matches any bank if `None`
+    pub bank: Option<u16>,
+    pub address: u16,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WatchKind {
+    Read,
+    Write,
+    /// Both read and write
+    Access,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Watchpoint {
+    pub address: u16,
+    pub kind: WatchKind,
+}
+
+/// Reason execution stopped
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Break {
+    /// About to execute the instruction at the breakpoint
+    Breakpoint { bank: u16, address: u16 },
+    /// The last instruction read the watched address
+    Read { address: u16, data: u8 },
+    /// The last instruction wrote to the watched address
+    Write { address: u16, data: u8 },
+}
+
+/// Breakpoints and watchpoints on CPU execution and memory accesses
+#[derive(Default)]
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+    enabled: bool,
+    hit: Option<Break>,
+    // The breakpoint at the current instruction has already been reported
+    resuming: bool,
+}
+
+impl Debugger {
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        if !self.breakpoints.contains(&breakpoint) {
+            self.breakpoints.push(breakpoint);
+        }
+    }
+
+    pub fn remove_breakpoint(&mut self, breakpoint: &Breakpoint) -> bool {
+        let len = self.breakpoints.len();
+        self.breakpoints.retain(|b| b != breakpoint);
+        self.breakpoints.len() != len
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        if !self.watchpoints.contains(&watchpoint) {
+            self.watchpoints.push(watchpoint);
+        }
+    }
+
+    pub fn remove_watchpoint(&mut self, watchpoint: &Watchpoint) -> bool {
+        let len = self.watchpoints.len();
+        self.watchpoints.retain(|w| w != watchpoint);
+        self.watchpoints.len() != len
+    }
+
+    pub fn clear(&mut self) {
+        self.breakpoints.clear();
+        self.watchpoints.clear();
+    }
+
+    /// Breakpoints and watchpoints are only checked while enabled
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.hit = None;
+        if !enabled {
+            self.resuming = false;
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn take_hit(&mut self) -> Option<Break> {
+        self.hit.take()
+    }
+
+    /// Returns true if execution should stop before the instruction at `address`
+    pub fn check_breakpoint(&mut self, bank: u16, address: u16) -> bool {
+        if !self.enabled || self.breakpoints.is_empty() {
+            return false;
+        }
+        if self.resuming {
+            self.resuming = false;
+            return false;
+        }
+        let hit = self
+            .breakpoints
+            .iter()
+            .any(|b| b.address == address && b.bank.is_none_or(|bank_| bank_ == bank));
+        if hit {
+            self.hit = Some(Break::Breakpoint { bank, address });
+            self.resuming = true;
+        }
+        hit
+    }
+
+    pub fn watch_read(&mut self, address: u16, data: u8) {
+        if self.watching(address, WatchKind::Read) {
+            self.hit = Some(Break::Read { address, data });
+        }
+    }
+
+    pub fn watch_write(&mut self, address: u16, data: u8) {
+        if self.watching(address, WatchKind::Write) {
+            self.hit = Some(Break::Write { address, data });
+        }
+    }
+
+    fn watching(&self, address: u16, kind: WatchKind) -> bool {
+        // Reports the first access in an instruction
+        self.enabled
+            && self.hit.is_none()
+            && self
+                .watchpoints
+                .iter()
+                .any(|w| w.address == address && (w.kind == kind || w.kind == WatchKind::Access))
+    }
+}
@@ -3,17 +3,31 @@ use meru_interface::{
 };
 
 use crate::{
-    config::{Config, Model},
+    cheat::{Cheat, CheatError},
+    config::{Config, Mbc1Multicart, MismatchedBackup, Model},
     consts,
     context::{self, Context},
-    interface::LinkCable,
+    cpu::{disassemble_with, Instruction, Register},
+    dat::GameEntry,
+    debugger::{Break, Debugger},
+    interface::{Camera, HostClock, Infrared, LinkCable, Rumble, SystemClock},
     io::Input,
-    rom::{CgbFlag, Mbc, Rom, RomError},
+    patch::PatchError,
+    rom::{CgbFlag, Mbc, Rom, RomError, RomReport},
+    search::{Candidate, Filter, MemorySearch, Snapshot, ValueSize},
+    trace::Tracer,
 };
 
 pub struct GameBoy {
     rom_hash: [u8; 32],
+    rom_report: Option<RomReport>,
+    game_entry: Option<GameEntry>,
     config: Config,
+    host_clock: Box<dyn HostClock + Send + Sync>,
+    rumble: Option<Rumble>,
+    memory_search: Option<MemorySearch>,
+    // Number of the frame being executed, `None` between frames
+    current_frame: Option<u64>,
     corrected_frame_buffer: FrameBuffer,
     ctx: context::Context,
 }
@@ -28,6 +42,18 @@ pub enum Error {
     RomHashMismatch,
     #[error("{0} is currently unsupported")]
     UnsupportedMbc(Mbc),
+    #[error("Cartridge has no battery backed memory, but backup data is given")]
+    BackupNotSupported,
+    #[error("Backup size mismatch: cartridge expected {expected}, but actual size is {actual}")]
+    BackupSizeMismatch { expected: usize, actual: usize },
+    #[error("{cartridge} does not support {rom_size} bytes of ROM with {ram_size} bytes of RAM")]
+    UnsupportedMemorySize {
+        cartridge: String,
+        rom_size: u64,
+        ram_size: u64,
+    },
+    #[error("{0}")]
+    PatchError(#[from] PatchError),
     #[error("deserialize failed: {0}")]
     DeserializeFailed(#[from] bincode::Error),
     #[error("{0}")]
@@ -40,6 +66,8 @@ const CORE_INFO: CoreInfo = CoreInfo {
     file_extensions: &["gb", "gbc"],
 };
 
+const VBLANK_LINE: u8 = consts::VISIBLE_RANGE.end as u8;
+
 fn default_key_config() -> KeyConfig {
     use meru_interface::key_assign::*;
 
@@ -53,6 +81,10 @@ fn default_key_config() -> KeyConfig {
         ("B", any!(keycode!(Z), pad_button!(0, South))),
         ("Start", any!(keycode!(Return), pad_button!(0, Start))),
         ("Select", any!(keycode!(RShift), pad_button!(0, Select))),
+        ("Tilt Up", any!(keycode!(I), pad_button!(0, North))),
+        ("Tilt Down", any!(keycode!(K), pad_button!(0, West))),
+        ("Tilt Left", any!(keycode!(J), pad_button!(0, LeftTrigger))),
+        ("Tilt Right", any!(keycode!(L), pad_button!(0, RightTrigger))),
     ];
 
     KeyConfig {
@@ -76,71 +108,35 @@ impl EmulatorCore for GameBoy {
     where
         Self: Sized,
     {
-        let rom = Rom::from_bytes(data)?;
-
-        let rom_hash = {
-            use sha2::Digest;
-            sha2::Sha256::digest(&rom.data).into()
-        };
-
-        let model = match rom.cgb_flag {
-            CgbFlag::NonCgb => {
-                if config.model == Model::Auto {
-                    Model::Dmg
-                } else {
-                    config.model
-                }
-            }
-            CgbFlag::SupportCgb => {
-                if config.model == Model::Auto {
-                    Model::Cgb
-                } else {
-                    config.model
-                }
-            }
-            CgbFlag::OnlyCgb => {
-                if config.model == Model::Dmg {
-                    Err(Error::DoesNotSupportCgb)?
-                } else {
-                    Model::Cgb
-                }
-            }
-        };
-
-        log::info!("Model: {model:?}");
-
-        let boot_rom = config.boot_roms()?.get(model).map(|r| r.to_owned());
-        let backup = backup.map(|r| r.to_vec());
-        let dmg_palette = config.palette();
-
-        let mut ret = Self {
-            rom_hash,
-            config: config.clone(),
-            corrected_frame_buffer: FrameBuffer::new(
-                consts::SCREEN_WIDTH as _,
-                consts::SCREEN_HEIGHT as _,
-            ),
-            ctx: Context::new(model, rom, &boot_rom, backup, dmg_palette)?,
-        };
-
-        if boot_rom.is_none() {
-            // Do not use boot ROM
-            // Set the values of the state after the boot ROM
-            ret.setup_initial_state();
-        }
-
-        Ok(ret)
+        Self::with_host_clock(data, backup, config, SystemClock)
     }
 
     fn game_info(&self) -> Vec<(String, String)> {
-        self.ctx
+        let mut ret: Vec<(String, String)> = self
+            .ctx
             .inner
             .inner
             .rom
             .info()
             .into_iter()
             .map(|(k, v)| (k.to_string(), v))
-            .collect()
+            .collect();
+
+        if let Some(game) = &self.game_entry {
+            ret.push(("Canonical Title".to_string(), game.title.clone()));
+            ret.push((
+                "Region".to_string(),
+                game.region.clone().unwrap_or_else(|| "Unknown".to_string()),
+            ));
+            ret.push((
+                "Revision".to_string(),
+                game.revision
+                    .clone()
+                    .unwrap_or_else(|| "Original".to_string()),
+            ));
+            ret.push(("Verified Good Dump".to_string(), game.verified.to_string()));
+        }
+        ret
     }
 
     fn set_config(&mut self, config: &Self::Config) {
@@ -150,32 +146,7 @@ impl EmulatorCore for GameBoy {
     }
 
     fn exec_frame(&mut self, render_graphics: bool) {
-        use context::*;
-
-        let mut audio_buffer = self.ctx.apu_mut().audio_buffer_mut();
-        audio_buffer.samples.clear();
-        audio_buffer.sample_rate = consts::AUDIO_SAMPLE_PER_FRAME as u32 * 60;
-
-        self.ctx
-            .ppu_mut()
-            .frame_buffer_mut()
-            .resize(consts::SCREEN_WIDTH as _, consts::SCREEN_HEIGHT as _);
-
-        self.ctx.ppu_mut().set_render_graphics(render_graphics);
-
-        let start_frame = self.ctx.ppu().frame();
-        while start_frame == self.ctx.ppu().frame() {
-            self.ctx.cpu.step(&mut self.ctx.inner);
-        }
-
-        if render_graphics {
-            let cc =
-                make_color_correction(self.ctx.model().is_cgb() && self.config.color_correction);
-            cc.convert_frame_buffer(
-                &mut self.corrected_frame_buffer,
-                self.ctx.ppu_mut().frame_buffer_mut(),
-            );
-        }
+        self.run_frame(render_graphics, false);
     }
 
     fn reset(&mut self) {
@@ -183,17 +154,32 @@ impl EmulatorCore for GameBoy {
 
         let model = self.ctx.model();
         let backup_ram = self.backup();
-        let mut rom = crate::rom::Rom::default();
-        std::mem::swap(&mut rom, self.ctx.rom_mut());
+        let rom = self.ctx.rom().clone();
 
         let boot_rom = self.ctx.inner.bus.boot_rom().clone();
         let dmg_palette = self.ctx.ppu().dmg_palette();
 
-        self.ctx = Context::new(model, rom, &boot_rom, backup_ram, dmg_palette).unwrap();
+        // The backup was made by this cartridge, so it is carried over regardless of the policy
+        let ctx = match Context::new(
+            model,
+            rom,
+            &boot_rom,
+            backup_ram,
+            MismatchedBackup::Resize,
+            dmg_palette,
+        ) {
+            Ok(ctx) => ctx,
+            Err(err) => {
+                log::error!("Failed to reset: {err}");
+                return;
+            }
+        };
+        self.replace_context(ctx);
 
         if boot_rom.is_none() {
             self.setup_initial_state();
         }
+        self.sync_rtc(false);
     }
 
     fn frame_buffer(&self) -> &FrameBuffer {
@@ -221,23 +207,32 @@ impl EmulatorCore for GameBoy {
                 "B" => gb_input.b = *value,
                 "Start" => gb_input.start = *value,
                 "Select" => gb_input.select = *value,
+                "Tilt Up" => gb_input.tilt_y -= *value as i32 as f32,
+                "Tilt Down" => gb_input.tilt_y += *value as i32 as f32,
+                "Tilt Left" => gb_input.tilt_x -= *value as i32 as f32,
+                "Tilt Right" => gb_input.tilt_x += *value as i32 as f32,
                 _ => unreachable!(),
             }
         }
 
-        let io = self.ctx.inner.bus.io();
-        io.set_input(&mut self.ctx.inner.inner, &gb_input);
+        self.set_raw_input(&gb_input);
     }
 
     fn backup(&self) -> Option<Vec<u8>> {
         use crate::mbc::MbcTrait;
+        use context::Rom;
+        if !self.ctx.rom().cartridge_type.has_battery {
+            return None;
+        }
         let external_ram = self.ctx.backup_ram();
         let internal_ram = self.ctx.inner.bus.mbc().internal_ram();
-        assert!(!(external_ram.is_some() && internal_ram.is_some()));
-        if external_ram.is_some() {
-            external_ram
-        } else {
-            internal_ram.map(|r| r.to_vec())
+        match (external_ram, internal_ram) {
+            (None, None) => None,
+            (external_ram, internal_ram) => {
+                let mut ret = external_ram.unwrap_or_default();
+                ret.extend(internal_ram.unwrap_or_default());
+                Some(ret)
+            }
         }
     }
 
@@ -259,7 +254,8 @@ impl EmulatorCore for GameBoy {
         }
 
         std::mem::swap(self.ctx.rom_mut(), ctx.rom_mut());
-        self.ctx = ctx;
+        self.replace_context(ctx);
+        self.sync_rtc(false);
 
         Ok(())
     }
@@ -315,6 +311,115 @@ impl ColorCorrection for CorrectColor {
 }
 
 impl GameBoy {
+    /// Creates a new instance with a host clock used for the cartridge RTC
+    pub fn with_host_clock(
+        data: &[u8],
+        backup: Option<&[u8]>,
+        config: &Config,
+        host_clock: impl HostClock + Send + Sync + 'static,
+    ) -> Result<Self, Error> {
+        let data = &config.patch_rom(data)?;
+        let (mut rom, rom_report) = if config.tolerant_rom_loading {
+            let (rom, report) = Rom::from_bytes_tolerant(data)?;
+            (rom, Some(report))
+        } else {
+            (Rom::from_bytes(data)?, None)
+        };
+
+        match config.mbc1_multicart {
+            Mbc1Multicart::Auto => {}
+            Mbc1Multicart::Force => rom.mbc1_multicart = true,
+            Mbc1Multicart::Disable => rom.mbc1_multicart = false,
+        }
+
+        let rom_hash = {
+            use sha2::Digest;
+            sha2::Sha256::digest(&rom.data).into()
+        };
+
+        let game_entry = match config.game_database() {
+            Ok(db) => db.and_then(|db| db.lookup(&rom_hash).cloned()),
+            Err(err) => {
+                log::warn!("Failed to load game database: {err}");
+                None
+            }
+        };
+        if let Some(game) = &game_entry {
+            log::info!("Game: {}", game.name);
+        }
+
+        let model = match rom.cgb_flag {
+            CgbFlag::NonCgb => {
+                if config.model == Model::Auto {
+                    Model::Dmg
+                } else {
+                    config.model
+                }
+            }
+            CgbFlag::SupportCgb => {
+                if config.model == Model::Auto {
+                    Model::Cgb
+                } else {
+                    config.model
+                }
+            }
+            CgbFlag::OnlyCgb => {
+                if config.model == Model::Dmg {
+                    Err(Error::DoesNotSupportCgb)?
+                } else {
+                    Model::Cgb
+                }
+            }
+        };
+
+        log::info!("Model: {model:?}");
+
+        let boot_rom = config.boot_roms()?.get(model).map(|r| r.to_owned());
+        let backup = backup.map(|r| r.to_vec());
+        let has_backup = backup.is_some();
+        let dmg_palette = config.palette();
+
+        let mut ret = Self {
+            rom_hash,
+            rom_report,
+            game_entry,
+            config: config.clone(),
+            host_clock: Box::new(host_clock),
+            rumble: None,
+            memory_search: None,
+            current_frame: None,
+            corrected_frame_buffer: FrameBuffer::new(
+                consts::SCREEN_WIDTH as _,
+                consts::SCREEN_HEIGHT as _,
+            ),
+            ctx: Context::new(
+                model,
+                rom,
+                &boot_rom,
+                backup,
+                config.mismatched_backup,
+                dmg_palette,
+            )?,
+        };
+
+        if boot_rom.is_none() {
+            // Do not use boot ROM
+            // Set the values of the state after the boot ROM
+            ret.setup_initial_state();
+        }
+        ret.sync_rtc(has_backup);
+
+        Ok(ret)
+    }
+
+    // Only a battery save advances the clock, so that save states replay the same way
+    fn sync_rtc(&mut self, battery_save: bool) {
+        use crate::mbc::MbcTrait;
+        let now = self.host_clock.now();
+        let advance = battery_save && self.config.sync_rtc_with_host;
+        self.ctx.inner.bus.mbc_mut().sync_rtc(now, advance);
+    }
+
     fn setup_initial_state(&mut self) {
         match context::Model::model(&self.ctx) {
             Model::Dmg => {
@@ -347,8 +452,264 @@ impl GameBoy {
         }
     }
 
+    /// Set input directly, for hosts that have analog input such as tilt sensors
+    pub fn set_raw_input(&mut self, input: &Input) {
+        use crate::mbc::MbcTrait;
+        let io = self.ctx.inner.bus.io();
+        io.set_input(&mut self.ctx.inner.inner, input);
+        self.ctx.inner.bus.mbc_mut().set_input(input);
+    }
+
     pub fn set_link_cable(&mut self, link_cable: Option<impl LinkCable + Send + Sync + 'static>) {
         let link_cable = link_cable.map(|r| Box::new(r) as Box<dyn LinkCable + Send + Sync>);
         self.ctx.inner.bus.io().set_link_cable(link_cable);
     }
+
+    pub fn set_infrared(&mut self, infrared: Option<impl Infrared + Send + Sync + 'static>) {
+        use crate::mbc::MbcTrait;
+        let infrared = infrared.map(|r| Box::new(r) as Box<dyn Infrared + Send + Sync>);
+        self.ctx.inner.bus.mbc_mut().set_infrared(infrared);
+    }
+
+    /// Validation report of the ROM image, only with `tolerant_rom_loading`
+    pub fn rom_report(&self) -> Option<&RomReport> {
+        self.rom_report.as_ref()
+    }
+
+    /// Entry of the game database matching the ROM
+    pub fn game_entry(&self) -> Option<&GameEntry> {
+        self.game_entry.as_ref()
+    }
+
+    /// Rumble motor state over the last frame, `None` if the cartridge has no motor
+    pub fn rumble(&self) -> Option<Rumble> {
+        self.rumble
+    }
+
+    pub fn set_camera(&mut self, camera: Option<impl Camera + Send + Sync + 'static>) {
+        use crate::mbc::MbcTrait;
+        let camera = camera.map(|r| Box::new(r) as Box<dyn Camera + Send + Sync>);
+        self.ctx.inner.bus.mbc_mut().set_camera(camera);
+    }
+
+    /// Adds an enabled Game Genie or GameShark code and returns its index
+    pub fn add_cheat(&mut self, code: &str) -> Result<usize, CheatError> {
+        self.ctx.inner.bus.cheats_mut().add(code)
+    }
+
+    pub fn remove_cheat(&mut self, index: usize) -> Option<Cheat> {
+        self.ctx.inner.bus.cheats_mut().remove(index)
+    }
+
+    pub fn set_cheat_enabled(&mut self, index: usize, enabled: bool) {
+        self.ctx.inner.bus.cheats_mut().set_enabled(index, enabled);
+    }
+
+    pub fn clear_cheats(&mut self) {
+        self.ctx.inner.bus.cheats_mut().clear();
+    }
+
+    pub fn cheats(&self) -> &[Cheat] {
+        self.ctx.inner.bus.cheats().list()
+    }
+
+    pub fn debugger(&self) -> &Debugger {
+        self.ctx.inner.bus.debugger()
+    }
+
+    pub fn debugger_mut(&mut self) -> &mut Debugger {
+        self.ctx.inner.bus.debugger_mut()
+    }
+
+    /// Sets the sink of per-instruction CPU trace logs, returning the previous one
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) -> Option<Tracer> {
+        std::mem::replace(self.ctx.inner.bus.tracer_mut(), tracer)
+    }
+
+    pub fn tracer_mut(&mut self) -> Option<&mut Tracer> {
+        self.ctx.inner.bus.tracer_mut().as_mut()
+    }
+
+    pub fn cpu_register(&mut self) -> &mut Register {
+        self.ctx.cpu.register()
+    }
+
+    /// Disassembles the instruction at `addr` in the current memory map
+    ///
+    /// IO registers are not read, so this returns `None` for instructions in $FF00-$FF7F.
+    pub fn disassemble(&mut self, addr: u16) -> Option<Instruction> {
+        use context::Bus;
+        disassemble_with(addr, |addr| self.ctx.inner.read_immutable(addr))
+    }
+
+    /// Runs until the end of the frame like `exec_frame`, but stops at breakpoints and watchpoints
+    ///
+    /// Calling this or `exec_frame` after a break resumes the rest of the frame.
+    pub fn run_until_break(&mut self, render_graphics: bool) -> Option<Break> {
+        self.run_frame(render_graphics, true)
+    }
+
+    /// Executes one instruction, or one machine cycle while the CPU is halted
+    ///
+    /// Cycles left from a previous run are consumed first, so this always starts a new instruction.
+    /// Breakpoints are not checked, so this steps over a breakpoint the debugger stopped at.
+    pub fn step_instruction(&mut self) {
+        let start = self.ctx.cpu.instruction_count();
+        self.run_until(false, |gb| {
+            let cpu = &gb.ctx.cpu;
+            !cpu.is_busy() && (cpu.instruction_count() != start || cpu.is_halting())
+        });
+    }
+
+    /// Runs `cycles` machine cycles, or until a breakpoint or watchpoint is hit
+    ///
+    /// The last instruction may exceed `cycles`, and the excess is taken into account on the next run.
+    pub fn run_cycles(&mut self, cycles: u64) -> Option<Break> {
+        if cycles == 0 {
+            return None;
+        }
+        let target = self.ctx.cpu.elapsed_cycles() + cycles;
+        self.run_until(true, |gb| gb.ctx.cpu.elapsed_cycles() >= target)
+    }
+
+    /// Runs until the PPU reaches the beginning of scanline `ly`, or until a break
+    pub fn run_until_scanline(&mut self, ly: u8) -> Option<Break> {
+        use context::Ppu;
+        assert!(
+            (ly as u64) < consts::LINES_PER_FRAME,
+            "invalid scanline: {ly}"
+        );
+        let mut prev = self.ctx.ppu().ly();
+        self.run_until(true, |gb| {
+            let cur = gb.ctx.ppu().ly();
+            let reached = prev != ly && cur == ly;
+            prev = cur;
+            reached
+        })
+    }
+
+    /// Runs until the beginning of VBlank, or until a break
+    pub fn run_until_vblank(&mut self) -> Option<Break> {
+        self.run_until_scanline(VBLANK_LINE)
+    }
+
+    fn run_frame(&mut self, render_graphics: bool, debug: bool) -> Option<Break> {
+        use context::Ppu;
+        self.ctx.ppu_mut().set_render_graphics(render_graphics);
+        let frame = self.ctx.ppu().frame();
+        self.run_until(debug, |gb| gb.ctx.ppu().frame() != frame)
+    }
+
+    /// Steps the CPU until `done` returns true or the debugger breaks, handling the frame boundaries
+    fn run_until(&mut self, debug: bool, mut done: impl FnMut(&Self) -> bool) -> Option<Break> {
+        use context::Ppu;
+
+        self.ctx.inner.bus.debugger_mut().set_enabled(debug);
+        loop {
+            let frame = match self.current_frame {
+                Some(frame) => frame,
+                None => self.begin_frame(),
+            };
+            let ly = self.ctx.ppu().ly();
+            self.ctx.cpu.step(&mut self.ctx.inner);
+            if ly != VBLANK_LINE && self.ctx.ppu().ly() == VBLANK_LINE {
+                // Written before the CPU dispatches the VBlank interrupt, like the real device
+                self.ctx
+                    .inner
+                    .bus
+                    .apply_game_shark(&mut self.ctx.inner.inner);
+            }
+            if frame != self.ctx.ppu().frame() {
+                self.end_frame();
+            }
+            if let Some(hit) = self.ctx.inner.bus.debugger_mut().take_hit() {
+                break Some(hit);
+            }
+            if done(self) {
+                break None;
+            }
+        }
+    }
+
+    fn begin_frame(&mut self) -> u64 {
+        use context::*;
+
+        let audio_buffer = self.ctx.apu_mut().audio_buffer_mut();
+        audio_buffer.samples.clear();
+        audio_buffer.sample_rate = consts::AUDIO_SAMPLE_PER_FRAME as u32 * 60;
+
+        self.ctx
+            .ppu_mut()
+            .frame_buffer_mut()
+            .resize(consts::SCREEN_WIDTH as _, consts::SCREEN_HEIGHT as _);
+
+        let frame = self.ctx.ppu().frame();
+        self.current_frame = Some(frame);
+        frame
+    }
+
+    fn end_frame(&mut self) {
+        use crate::mbc::MbcTrait;
+        use context::*;
+
+        self.current_frame = None;
+
+        self.rumble = self.ctx.inner.bus.mbc_mut().take_rumble();
+
+        if self.ctx.ppu().render_graphics() {
+            let cc =
+                make_color_correction(self.ctx.model().is_cgb() && self.config.color_correction);
+            cc.convert_frame_buffer(
+                &mut self.corrected_frame_buffer,
+                self.ctx.ppu_mut().frame_buffer_mut(),
+            );
+        }
+    }
+
+    // Moves states kept by the host to the new context.
+    // Cheats, the debugger and the tracer are not serialized, so they survive loading save states.
+    fn replace_context(&mut self, mut ctx: Context) {
+        std::mem::swap(self.ctx.inner.bus.cheats_mut(), ctx.inner.bus.cheats_mut());
+        std::mem::swap(
+            self.ctx.inner.bus.debugger_mut(),
+            ctx.inner.bus.debugger_mut(),
+        );
+        std::mem::swap(self.ctx.inner.bus.tracer_mut(), ctx.inner.bus.tracer_mut());
+        self.ctx = ctx;
+        self.current_frame = None;
+    }
+
+    /// Current contents of WRAM, HRAM and SRAM
+    pub fn memory_snapshot(&self) -> Snapshot {
+        use context::ExternalRam;
+        Snapshot {
+            wram: self.ctx.inner.bus.wram().to_vec(),
+            hram: self.ctx.inner.bus.hram().to_vec(),
+            sram: self.ctx.external_ram().to_vec(),
+        }
+    }
+
+    /// Starts a new memory search, `bcd` reads values as packed BCD
+    pub fn start_memory_search(&mut self, size: ValueSize, bcd: bool) -> &[Candidate] {
+        let search = MemorySearch::new(&self.memory_snapshot(), size, bcd);
+        self.memory_search.insert(search).candidates()
+    }
+
+    /// Narrows down the candidates of the current memory search
+    pub fn filter_memory_search(&mut self, filter: Filter) -> &[Candidate] {
+        let snapshot = self.memory_snapshot();
+        match &mut self.memory_search {
+            Some(search) => {
+                search.filter(&snapshot, filter);
+                search.candidates()
+            }
+            None => &[],
+        }
+    }
+
+    pub fn memory_search_candidates(&self) -> &[Candidate] {
+        self.memory_search
+            .as_ref()
+            .map_or(&[], |search| search.candidates())
+    }
 }
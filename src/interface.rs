@@ -2,3 +2,38 @@ pub trait LinkCable {
     fn send(&mut self, data: u8);
     fn try_recv(&mut self) -> Option<u8>;
 }
+
+pub trait Infrared {
+    /// Called when the cartridge turns its IR LED on or off
+    fn send(&mut self, led_on: bool);
+    /// Returns the light level seen by the IR receiver if it has changed
+    fn try_recv(&mut self) -> Option<bool>;
+}
+
+pub trait Camera {
+    /// Fills `frame` with a `CAMERA_WIDTH` x `CAMERA_HEIGHT` grayscale image in row-major order, 0 is black and 255 is white
+    fn capture(&mut self, frame: &mut [u8]);
+}
+
+/// Rumble motor state over a frame
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct Rumble {
+    /// Motor is on at the end of the frame
+    pub active: bool,
+    /// Fraction of the frame the motor was on, from 0.0 to 1.0
+    pub intensity: f32,
+}
+
+pub trait HostClock {
+    /// Returns the current time in seconds since the Unix epoch
+    fn now(&self) -> i64;
+}
+
+/// Host clock backed by the system time
+pub struct SystemClock;
+
+impl HostClock for SystemClock {
+    fn now(&self) -> i64 {
+        chrono::Utc::now().timestamp()
+    }
+}
@@ -40,6 +40,10 @@ pub struct Input {
     pub down: bool,
     pub left: bool,
     pub right: bool,
+    /// Tilt for cartridges with an accelerometer, from -1.0 (left) to 1.0 (right)
+    pub tilt_x: f32,
+    /// Tilt for cartridges with an accelerometer, from -1.0 (up) to 1.0 (down)
+    pub tilt_y: f32,
 }
 
 impl Default for Io {
@@ -2,22 +2,29 @@
 
 pub mod apu;
 pub mod bus;
+pub mod cheat;
 pub mod config;
 pub mod consts;
 pub mod context;
 pub mod cpu;
+pub mod dat;
+pub mod debugger;
 pub mod gameboy;
 pub mod interface;
 pub mod io;
+pub mod licensee;
 pub mod mbc;
+pub mod patch;
 pub mod ppu;
 pub mod rom;
+pub mod search;
 pub mod serial;
+pub mod trace;
 pub mod util;
 
 pub use crate::{
     config::{BootRoms, Config, Model},
     gameboy::GameBoy,
-    interface::LinkCable,
+    interface::{Camera, HostClock, Infrared, LinkCable, Rumble, SystemClock},
     rom::Rom,
 };
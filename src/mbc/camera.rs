@@ -0,0 +1,218 @@
+use bitvec::prelude::*;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    consts::{CAMERA_HEIGHT, CAMERA_WIDTH},
+    gameboy::Error,
+    interface::Camera,
+    mbc::{check_internal_ram, ram_bank_num, rom_bank_num, Context},
+    rom::Rom,
+};
+
+const REGISTER_NUM: usize = 0x36;
+const IMAGE_OFFSET: usize = 0x100;
+
+// Edge enhancement ratio selected by bits 4-6 of register 4
+const EDGE_RATIO: [f32; 8] = [0.50, 0.75, 1.00, 1.25, 2.00, 3.00, 4.00, 5.00];
+
+/// Pocket Camera cartridge with Mitsubishi M64282FP image sensor
+///
+/// The analog part of the sensor is approximated: the captured image is scaled
+/// by exposure time and gain, edge enhanced, inverted if requested and then
+/// quantized with the dither matrix. Output reference voltage and zero point
+/// calibration are not modeled.
+#[derive(Serialize, Deserialize)]
+pub struct PocketCamera {
+    rom_bank: u8,
+    rom_bank_mask: u8,
+    ram_bank: u8,
+    ram_bank_mask: u8,
+    ram_enable: bool,
+    #[serde(with = "serde_bytes")]
+    regs: Vec<u8>,
+    busy_dots: u64,
+    #[serde(skip)]
+    camera: Option<Box<dyn Camera + Send + Sync>>,
+}
+
+impl PocketCamera {
+    pub fn new(rom: &Rom, internal_ram: Option<Vec<u8>>) -> Result<Self, Error> {
+        check_internal_ram(&internal_ram, 0)?;
+        let rom_bank_num = rom_bank_num(rom, 0x4000);
+        let ram_bank_num = ram_bank_num(rom, 0x2000)?;
+        Ok(Self {
+            rom_bank: 1,
+            rom_bank_mask: rom_bank_num.saturating_sub(1) as u8,
+            ram_bank: 0,
+            ram_bank_mask: ram_bank_num.saturating_sub(1) as u8,
+            ram_enable: false,
+            regs: vec![0; REGISTER_NUM],
+            busy_dots: 0,
+            camera: None,
+        })
+    }
+
+    fn start_capture(&mut self) {
+        let exposure = u16::from_be_bytes([self.regs[2], self.regs[3]]) as u64;
+        let exclusive_edge = self.regs[1] & 0x80 != 0;
+        // Capture time is counted in CPU cycles at normal speed
+        let cycles = 32446 + if exclusive_edge { 0 } else { 512 } + 16 * exposure;
+        debug!("Camera: Capture start: exposure = ${exposure:04X}, {cycles} cycles");
+        self.busy_dots = cycles * 4;
+    }
+
+    fn sense(&mut self) -> Vec<f32> {
+        let (width, height) = (CAMERA_WIDTH as usize, CAMERA_HEIGHT as usize);
+        let mut frame = vec![0; width * height];
+        if let Some(camera) = &mut self.camera {
+            camera.capture(&mut frame);
+        }
+
+        // Each gain step adds about 1.5dB
+        let gain = 10.0f32.powf((self.regs[1] & 0x1F) as f32 * 1.5 / 20.0);
+        let exposure = u16::from_be_bytes([self.regs[2], self.regs[3]]) as f32 / 0x1000 as f32;
+        frame
+            .into_iter()
+            .map(|p| p as f32 * exposure * gain)
+            .collect()
+    }
+
+    fn finish_capture(&mut self, ctx: &mut impl Context) {
+        let (width, height) = (CAMERA_WIDTH as usize, CAMERA_HEIGHT as usize);
+        let image = self.sense();
+        let pixel = |x: isize, y: isize| {
+            let x = x.clamp(0, width as isize - 1) as usize;
+            let y = y.clamp(0, height as isize - 1) as usize;
+            image[y * width + x]
+        };
+
+        let edge_ratio = EDGE_RATIO[(self.regs[4] as usize >> 4) & 7];
+        let edge_mode = (self.regs[1] >> 5) & 3;
+        let invert = self.regs[4] & 0x08 != 0;
+
+        if ctx.external_ram().len() < IMAGE_OFFSET + width * height / 4 {
+            warn!("Camera: No RAM to store the image");
+            return;
+        }
+
+        for y in 0..height {
+            for x in 0..width {
+                let (x, y) = (x as isize, y as isize);
+                let p = pixel(x, y);
+                let edge = match edge_mode {
+                    0 => 0.0,
+                    1 => 2.0 * p - pixel(x - 1, y) - pixel(x + 1, y),
+                    2 => 2.0 * p - pixel(x, y - 1) - pixel(x, y + 1),
+                    _ => {
+                        4.0 * p
+                            - pixel(x - 1, y)
+                            - pixel(x + 1, y)
+                            - pixel(x, y - 1)
+                            - pixel(x, y + 1)
+                    }
+                };
+                let mut level = (p + edge * edge_ratio).clamp(0.0, 255.0) as u8;
+                if invert {
+                    level = !level;
+                }
+
+                let (x, y) = (x as usize, y as usize);
+                let matrix = &self.regs[6 + ((y & 3) * 4 + (x & 3)) * 3..][..3];
+                let color = matrix
+                    .iter()
+                    .filter(|&&threshold| level < threshold)
+                    .count() as u8;
+
+                let tile = (y / 8) * (width / 8) + x / 8;
+                let offset = IMAGE_OFFSET + tile * 16 + (y % 8) * 2;
+                let ram = ctx.external_ram_mut();
+                let bit = 7 - x % 8;
+                ram[offset].view_bits_mut::<Lsb0>().set(bit, color & 1 != 0);
+                ram[offset + 1]
+                    .view_bits_mut::<Lsb0>()
+                    .set(bit, color & 2 != 0);
+            }
+        }
+    }
+}
+
+impl super::MbcTrait for PocketCamera {
+    fn read(&mut self, ctx: &mut impl Context, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3FFF => ctx.rom().data[addr as usize],
+            0x4000..=0x7FFF => {
+                let offset = (self.rom_bank & self.rom_bank_mask) as usize * 0x4000;
+                ctx.rom().data[offset + (addr & 0x3FFF) as usize]
+            }
+            0xA000..=0xBFFF => {
+                if self.ram_bank & 0x10 != 0 {
+                    // Only the capture register is readable
+                    if addr & 0x7F == 0 {
+                        (self.regs[0] & 0x06) | (self.busy_dots > 0) as u8
+                    } else {
+                        0x00
+                    }
+                } else if self.busy_dots > 0 {
+                    // RAM is not accessible while capturing
+                    0x00
+                } else if self.ram_enable && !ctx.external_ram().is_empty() {
+                    let offset = (self.ram_bank & self.ram_bank_mask) as usize * 0x2000;
+                    ctx.external_ram()[offset + (addr & 0x1FFF) as usize]
+                } else {
+                    !0
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn rom_bank(&self, addr: u16) -> u16 {
+        if addr < 0x4000 {
+            0
+        } else {
+            (self.rom_bank & self.rom_bank_mask) as u16
+        }
+    }
+
+    fn write(&mut self, ctx: &mut impl Context, addr: u16, data: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.ram_enable = data & 0x0F == 0x0A,
+            0x2000..=0x3FFF => self.rom_bank = data & 0x3F,
+            0x4000..=0x5FFF => self.ram_bank = data & 0x1F,
+            0x6000..=0x7FFF => {}
+            0xA000..=0xBFFF => {
+                if self.ram_bank & 0x10 != 0 {
+                    let index = (addr & 0x7F) as usize;
+                    if index == 0 {
+                        self.regs[0] = data & 0x07;
+                        if data & 1 != 0 && self.busy_dots == 0 {
+                            self.start_capture();
+                        }
+                    } else if index < REGISTER_NUM {
+                        self.regs[index] = data;
+                    }
+                } else if self.ram_enable && self.busy_dots == 0 && !ctx.external_ram().is_empty() {
+                    let offset = (self.ram_bank & self.ram_bank_mask) as usize * 0x2000;
+                    ctx.external_ram_mut()[offset + (addr & 0x1FFF) as usize] = data;
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn tick(&mut self, ctx: &mut impl Context, dots: u64) {
+        if self.busy_dots == 0 {
+            return;
+        }
+        self.busy_dots = self.busy_dots.saturating_sub(dots);
+        if self.busy_dots == 0 {
+            debug!("Camera: Capture finished");
+            self.finish_capture(ctx);
+        }
+    }
+
+    fn set_camera(&mut self, camera: Option<Box<dyn Camera + Send + Sync>>) {
+        self.camera = camera;
+    }
+}
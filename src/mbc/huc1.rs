@@ -0,0 +1,87 @@
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    gameboy::Error,
+    interface::Infrared,
+    mbc::{check_internal_ram, ram_bank_num, rom_bank_num, Context, IrPort},
+    rom::Rom,
+};
+
+#[derive(Serialize, Deserialize)]
+pub struct HuC1 {
+    rom_bank: u8,
+    rom_bank_mask: u8,
+    ram_bank: u8,
+    ram_bank_mask: u8,
+    ir_mode: bool,
+    ir: IrPort,
+}
+
+impl HuC1 {
+    pub fn new(rom: &Rom, internal_ram: Option<Vec<u8>>) -> Result<Self, Error> {
+        check_internal_ram(&internal_ram, 0)?;
+        let rom_bank_num = rom_bank_num(rom, 0x4000);
+        let ram_bank_num = ram_bank_num(rom, 0x2000)?;
+        Ok(Self {
+            rom_bank: 1,
+            rom_bank_mask: rom_bank_num.saturating_sub(1) as u8,
+            ram_bank: 0,
+            ram_bank_mask: ram_bank_num.saturating_sub(1) as u8,
+            ir_mode: false,
+            ir: IrPort::default(),
+        })
+    }
+}
+
+impl super::MbcTrait for HuC1 {
+    fn read(&mut self, ctx: &mut impl Context, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3FFF => ctx.rom().data[addr as usize],
+            0x4000..=0x7FFF => {
+                let offset = (self.rom_bank & self.rom_bank_mask) as usize * 0x4000;
+                ctx.rom().data[offset + (addr & 0x3FFF) as usize]
+            }
+            0xA000..=0xBFFF => {
+                if self.ir_mode {
+                    self.ir.read()
+                } else if !ctx.external_ram().is_empty() {
+                    let offset = (self.ram_bank & self.ram_bank_mask) as usize * 0x2000;
+                    ctx.external_ram()[offset + (addr & 0x1FFF) as usize]
+                } else {
+                    !0
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn rom_bank(&self, addr: u16) -> u16 {
+        if addr < 0x4000 {
+            0
+        } else {
+            (self.rom_bank & self.rom_bank_mask) as u16
+        }
+    }
+
+    fn write(&mut self, ctx: &mut impl Context, addr: u16, data: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.ir_mode = data == 0x0E,
+            0x2000..=0x3FFF => self.rom_bank = data & 0x3F,
+            0x4000..=0x5FFF => self.ram_bank = data & 0x03,
+            0xA000..=0xBFFF => {
+                if self.ir_mode {
+                    self.ir.write(data);
+                } else if !ctx.external_ram().is_empty() {
+                    let offset = (self.ram_bank & self.ram_bank_mask) as usize * 0x2000;
+                    ctx.external_ram_mut()[offset + (addr & 0x1FFF) as usize] = data;
+                }
+            }
+            _ => warn!("HuC1: Write ${addr:04X} = ${data:02X}"),
+        }
+    }
+
+    fn set_infrared(&mut self, infrared: Option<Box<dyn Infrared + Send + Sync>>) {
+        self.ir.set_infrared(infrared);
+    }
+}
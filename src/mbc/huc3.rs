@@ -0,0 +1,236 @@
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    consts::DOTS_PER_SECOND,
+    gameboy::Error,
+    interface::Infrared,
+    mbc::{check_internal_ram, ram_bank_num, rom_bank_num, Context, HostTime, IrPort},
+    rom::Rom,
+};
+
+const DOTS_PER_MINUTE: u64 = DOTS_PER_SECOND * 60;
+const MINUTES_PER_DAY: u16 = 24 * 60;
+
+// Last RTC time (u64), minutes, days, alarm minutes, alarm days (u16), alarm enable (u8)
+pub const RTC_SAVE_SIZE: usize = 17;
+
+#[derive(Serialize, Deserialize)]
+pub struct HuC3 {
+    rom_bank: u8,
+    rom_bank_mask: u8,
+    ram_bank: u8,
+    ram_bank_mask: u8,
+    mode: u8,
+    rtc: Rtc,
+    ir: IrPort,
+}
+
+/// Clock and nibble memory of the microcontroller, accessed through the command register
+#[derive(Serialize, Deserialize)]
+struct Rtc {
+    minutes: u16,
+    days: u16,
+    counter: u64,
+    #[serde(with = "serde_bytes")]
+    memory: Vec<u8>,
+    addr: u8,
+    result: u8,
+    host_time: HostTime,
+}
+
+impl HuC3 {
+    pub fn new(rom: &Rom, internal_ram: Option<Vec<u8>>) -> Result<Self, Error> {
+        check_internal_ram(&internal_ram, RTC_SAVE_SIZE)?;
+        let rom_bank_num = rom_bank_num(rom, 0x4000);
+        let ram_bank_num = ram_bank_num(rom, 0x2000)?;
+
+        let mut rtc = Rtc {
+            minutes: 0,
+            days: 0,
+            counter: 0,
+            memory: vec![0; 0x100],
+            addr: 0,
+            result: 0,
+            host_time: HostTime::default(),
+        };
+        if let Some(data) = internal_ram {
+            rtc.load(&data);
+        }
+
+        Ok(Self {
+            rom_bank: 1,
+            rom_bank_mask: rom_bank_num.saturating_sub(1) as u8,
+            ram_bank: 0,
+            ram_bank_mask: ram_bank_num.saturating_sub(1) as u8,
+            mode: 0,
+            rtc,
+            ir: IrPort::default(),
+        })
+    }
+}
+
+impl Rtc {
+    fn read_nibbles(&self, addr: usize, len: usize) -> u16 {
+        (0..len).fold(0, |acc, i| {
+            acc | (self.memory[addr + i] as u16 & 0xF) << (i * 4)
+        })
+    }
+
+    fn write_nibbles(&mut self, addr: usize, len: usize, data: u16) {
+        for i in 0..len {
+            self.memory[addr + i] = (data >> (i * 4)) as u8 & 0xF;
+        }
+    }
+
+    fn advance_dots(&mut self, dots: u64) {
+        self.counter += dots;
+        let minutes = self.counter / DOTS_PER_MINUTE;
+        self.counter %= DOTS_PER_MINUTE;
+
+        let total = self.minutes as u64 + minutes;
+        self.minutes = (total % MINUTES_PER_DAY as u64) as u16;
+        self.days = self
+            .days
+            .wrapping_add((total / MINUTES_PER_DAY as u64) as u16);
+    }
+
+    fn tick(&mut self, dots: u64) {
+        self.host_time.tick(dots);
+        self.advance_dots(dots);
+    }
+
+    fn load(&mut self, data: &[u8]) {
+        let u16_at = |i: usize| u16::from_le_bytes([data[i], data[i + 1]]);
+        let last_time = i64::from_le_bytes(data[0..8].try_into().unwrap());
+        self.minutes = u16_at(8) % MINUTES_PER_DAY;
+        self.days = u16_at(10);
+        self.write_nibbles(0x58, 3, u16_at(12));
+        self.write_nibbles(0x5B, 4, u16_at(14));
+        self.memory[0x5F] = data[16] & 1;
+        self.host_time = HostTime::new(last_time);
+    }
+
+    fn save(&self) -> Vec<u8> {
+        let last_time = self.host_time.now() - (self.counter / DOTS_PER_SECOND) as i64;
+        let mut ret = Vec::with_capacity(RTC_SAVE_SIZE);
+        ret.extend_from_slice(&last_time.to_le_bytes());
+        ret.extend_from_slice(&self.minutes.to_le_bytes());
+        ret.extend_from_slice(&self.days.to_le_bytes());
+        ret.extend_from_slice(&self.read_nibbles(0x58, 3).to_le_bytes());
+        ret.extend_from_slice(&self.read_nibbles(0x5B, 4).to_le_bytes());
+        ret.push(self.memory[0x5F] & 1);
+        ret
+    }
+}
+
+impl HuC3 {
+    fn command(&mut self, data: u8) {
+        let arg = data & 0xF;
+        let rtc = &mut self.rtc;
+        match data >> 4 {
+            // Read memory and increment address
+            0x1 => {
+                rtc.result = rtc.memory[rtc.addr as usize] & 0xF;
+                rtc.addr = rtc.addr.wrapping_add(1);
+            }
+            // Write memory and increment address
+            0x3 => {
+                rtc.memory[rtc.addr as usize] = arg;
+                rtc.addr = rtc.addr.wrapping_add(1);
+            }
+            0x4 => rtc.addr = (rtc.addr & 0xF0) | arg,
+            0x5 => rtc.addr = (rtc.addr & 0x0F) | arg << 4,
+            0x6 => match arg {
+                // Copy current time to memory
+                0x0 => {
+                    let (minutes, days) = (rtc.minutes, rtc.days);
+                    rtc.write_nibbles(0x00, 3, minutes);
+                    rtc.write_nibbles(0x03, 4, days);
+                }
+                // Set current time from memory
+                0x1 => {
+                    rtc.minutes = rtc.read_nibbles(0x00, 3) % MINUTES_PER_DAY;
+                    rtc.days = rtc.read_nibbles(0x03, 4);
+                    rtc.counter = 0;
+                }
+                // Status
+                0x2 => rtc.result = 1,
+                // Play tone, the speaker is not emulated
+                0xE => debug!("HuC3: Tone: {:X}", rtc.memory[0x27]),
+                _ => warn!("HuC3: Unknown extended command: ${data:02X}"),
+            },
+            _ => warn!("HuC3: Unknown command: ${data:02X}"),
+        }
+    }
+}
+
+impl super::MbcTrait for HuC3 {
+    fn read(&mut self, ctx: &mut impl Context, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3FFF => ctx.rom().data[addr as usize],
+            0x4000..=0x7FFF => {
+                let offset = (self.rom_bank & self.rom_bank_mask) as usize * 0x4000;
+                ctx.rom().data[offset + (addr & 0x3FFF) as usize]
+            }
+            0xA000..=0xBFFF => match self.mode {
+                0x0 | 0xA if !ctx.external_ram().is_empty() => {
+                    let offset = (self.ram_bank & self.ram_bank_mask) as usize * 0x2000;
+                    ctx.external_ram()[offset + (addr & 0x1FFF) as usize]
+                }
+                // Command result
+                0xC => self.rtc.result,
+                // Semaphore, always ready
+                0xD => 0x01,
+                0xE => self.ir.read(),
+                _ => 0x01,
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    fn rom_bank(&self, addr: u16) -> u16 {
+        if addr < 0x4000 {
+            0
+        } else {
+            (self.rom_bank & self.rom_bank_mask) as u16
+        }
+    }
+
+    fn write(&mut self, ctx: &mut impl Context, addr: u16, data: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.mode = data & 0x0F,
+            0x2000..=0x3FFF => self.rom_bank = data & 0x7F,
+            0x4000..=0x5FFF => self.ram_bank = data & 0x03,
+            0xA000..=0xBFFF => match self.mode {
+                0xA if !ctx.external_ram().is_empty() => {
+                    let offset = (self.ram_bank & self.ram_bank_mask) as usize * 0x2000;
+                    ctx.external_ram_mut()[offset + (addr & 0x1FFF) as usize] = data;
+                }
+                0xB => self.command(data),
+                0xE => self.ir.write(data),
+                _ => {}
+            },
+            _ => warn!("HuC3: Write ${addr:04X} = ${data:02X}"),
+        }
+    }
+
+    fn tick(&mut self, _ctx: &mut impl Context, dots: u64) {
+        self.rtc.tick(dots);
+    }
+
+    fn internal_ram(&self) -> Option<Vec<u8>> {
+        Some(self.rtc.save())
+    }
+
+    fn set_infrared(&mut self, infrared: Option<Box<dyn Infrared + Send + Sync>>) {
+        self.ir.set_infrared(infrared);
+    }
+
+    fn sync_rtc(&mut self, now: i64, advance: bool) {
+        let elapsed = self.rtc.host_time.sync(now);
+        if advance {
+            self.rtc.advance_dots(elapsed * DOTS_PER_SECOND);
+        }
+    }
+}
@@ -2,7 +2,11 @@ use bitvec::prelude::*;
 use serde::{Deserialize, Serialize};
 use std::cmp::max;
 
-use crate::{mbc::Context, rom::Rom};
+use crate::{
+    gameboy::Error,
+    mbc::{check_internal_ram, ram_bank_num, rom_bank_num, unsupported_memory_size, Context},
+    rom::Rom,
+};
 
 #[derive(Serialize, Deserialize)]
 pub struct Mbc1 {
@@ -13,18 +17,21 @@ pub struct Mbc1 {
     ram_size_mask: u16,
     ram_enable: bool,
     banking_mode: bool,
+    // Upper bank bits are wired to bit 5-6 normally, and bit 4-5 on multicarts
+    high_bits_shift: u8,
 }
 
 impl Mbc1 {
-    pub fn new(rom: &Rom, internal_ram: Option<Vec<u8>>) -> Self {
-        assert!(internal_ram.is_none());
-        assert!(!(rom.rom_size >= 1024 * 1024 && rom.ram_size >= 32 * 1024));
+    pub fn new(rom: &Rom, internal_ram: Option<Vec<u8>>) -> Result<Self, Error> {
+        check_internal_ram(&internal_ram, 0)?;
+        // Upper bank bits are shared between ROM and RAM
+        if rom.rom_size >= 1024 * 1024 && rom.ram_size >= 32 * 1024 {
+            Err(unsupported_memory_size(rom))?
+        }
 
-        let rom_bank_num = rom.rom_size / 0x4000;
-        assert!(rom_bank_num.is_power_of_two());
-        let ram_bank_num = rom.ram_size / 0x2000;
-        assert!(rom.ram_size == 0 || ram_bank_num.is_power_of_two());
-        Self {
+        let rom_bank_num = rom_bank_num(rom, 0x4000);
+        let ram_bank_num = ram_bank_num(rom, 0x2000)?;
+        Ok(Self {
             rom_bank: 1,
             high_bits: 0,
             rom_bank_mask: rom_bank_num.saturating_sub(1) as u8,
@@ -32,7 +39,12 @@ impl Mbc1 {
             ram_size_mask: rom.ram_size.saturating_sub(1) as u16,
             ram_enable: false,
             banking_mode: false,
-        }
+            high_bits_shift: if rom.mbc1_multicart { 4 } else { 5 },
+        })
+    }
+
+    fn low_bits(&self) -> u8 {
+        self.rom_bank & ((1 << self.high_bits_shift) - 1)
     }
 }
 
@@ -43,12 +55,13 @@ impl super::MbcTrait for Mbc1 {
                 let rom_bank = if !self.banking_mode {
                     0
                 } else {
-                    (self.high_bits << 5) & self.rom_bank_mask
+                    (self.high_bits << self.high_bits_shift) & self.rom_bank_mask
                 };
                 ctx.rom().data[rom_bank as usize * 0x4000 + addr as usize]
             }
             0x4000..=0x7FFF => {
-                let rom_bank = (self.high_bits << 5 | self.rom_bank) & self.rom_bank_mask;
+                let rom_bank =
+                    (self.high_bits << self.high_bits_shift | self.low_bits()) & self.rom_bank_mask;
                 ctx.rom().data[rom_bank as usize * 0x4000 + (addr & 0x3FFF) as usize]
             }
             0xA000..=0xBFFF => {
@@ -68,6 +81,16 @@ impl super::MbcTrait for Mbc1 {
         }
     }
 
+    fn rom_bank(&self, addr: u16) -> u16 {
+        let low_bits = if addr < 0x4000 { 0 } else { self.low_bits() };
+        let high_bits = if addr < 0x4000 && !self.banking_mode {
+            0
+        } else {
+            self.high_bits << self.high_bits_shift
+        };
+        ((high_bits | low_bits) & self.rom_bank_mask) as u16
+    }
+
     fn write(&mut self, ctx: &mut impl Context, addr: u16, data: u8) {
         match addr {
             0x0000..=0x1FFF => {
@@ -3,6 +3,13 @@ use log::warn;
 use serde::{Deserialize, Serialize};
 use std::cmp::max;
 
+use crate::{
+    gameboy::Error,
+    mbc::{check_internal_ram, rom_bank_num},
+};
+
+pub const RAM_SIZE: usize = 0x100;
+
 #[derive(Serialize, Deserialize)]
 pub struct Mbc2 {
     rom_bank: u8,
@@ -13,18 +20,15 @@ pub struct Mbc2 {
 }
 
 impl Mbc2 {
-    pub fn new(rom: &crate::rom::Rom, internal_ram: Option<Vec<u8>>) -> Self {
-        if let Some(ram) = &internal_ram {
-            assert_eq!(ram.len(), 256);
-        }
-        let rom_bank_num = rom.rom_size / 0x4000;
-        assert!(rom_bank_num.is_power_of_two());
-        Self {
+    pub fn new(rom: &crate::rom::Rom, internal_ram: Option<Vec<u8>>) -> Result<Self, Error> {
+        check_internal_ram(&internal_ram, RAM_SIZE)?;
+        let rom_bank_num = rom_bank_num(rom, 0x4000);
+        Ok(Self {
             rom_bank: 1,
             rom_bank_mask: rom_bank_num.saturating_sub(1) as u8,
-            ram: internal_ram.unwrap_or_else(|| vec![0; 0x100]),
+            ram: internal_ram.unwrap_or_else(|| vec![0; RAM_SIZE]),
             ram_enable: false,
-        }
+        })
     }
 }
 
@@ -52,6 +56,14 @@ impl super::MbcTrait for Mbc2 {
             _ => panic!("MBC2: Read ${addr:04X}"),
         }
     }
+
+    fn rom_bank(&self, addr: u16) -> u16 {
+        if addr < 0x4000 {
+            0
+        } else {
+            (self.rom_bank & self.rom_bank_mask) as u16
+        }
+    }
     fn write(&mut self, _ctx: &mut impl super::Context, addr: u16, data: u8) {
         match addr {
             0x0000..=0x3FFF => {
@@ -75,7 +87,7 @@ impl super::MbcTrait for Mbc2 {
             _ => warn!("MBC2: Write ${addr:04X} = ${data:02X}"),
         }
     }
-    fn internal_ram(&self) -> Option<&[u8]> {
-        Some(&self.ram)
+    fn internal_ram(&self) -> Option<Vec<u8>> {
+        Some(self.ram.clone())
     }
 }
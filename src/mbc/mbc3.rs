@@ -1,9 +1,19 @@
-use chrono::prelude::*;
-use log::warn;
+use log::{info, warn};
 use serde::{Deserialize, Serialize};
 use std::cmp::max;
 
-use crate::{mbc::Context, rom::Rom, util::pack};
+use crate::{
+    consts::DOTS_PER_SECOND,
+    gameboy::Error,
+    mbc::{ram_bank_num, rom_bank_num, Context, HostTime},
+    rom::Rom,
+    util::pack,
+};
+
+// Current and latched seconds, minutes, hours, days low, days high (u32 each), UNIX time (u64)
+// Compatible with BGB, VBA-M and SameBoy. Older files store the time as u32.
+const RTC_SAVE_SIZE: usize = 48;
+const RTC_SAVE_SIZE_32BIT: usize = 44;
 
 #[derive(Serialize, Deserialize)]
 pub struct Mbc3 {
@@ -12,9 +22,12 @@ pub struct Mbc3 {
     ram_bank_or_timer: RamBankOrTimer,
     ram_bank_mask: u8,
     ram_and_timer_enable: bool,
-    clock_data_latch: DateTime<Utc>,
+    rtc: Rtc,
+    rtc_latch: [u8; 5],
     clock_data_req: u8,
-    day_counter_carry: bool,
+    // MBC30 has 8-bit ROM bank register and 8 RAM banks
+    mbc30: bool,
+    has_timer: bool,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -24,22 +37,161 @@ enum RamBankOrTimer {
     Invalid,
 }
 
+/// Real time clock counted with emulated time
+#[derive(Default, Serialize, Deserialize)]
+struct Rtc {
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    days: u16,
+    halt: bool,
+    day_counter_carry: bool,
+    sub_second: u64,
+    host_time: HostTime,
+}
+
 impl Mbc3 {
-    pub fn new(rom: &Rom, internal_ram: Option<Vec<u8>>) -> Self {
-        assert!(internal_ram.is_none());
-        let rom_bank_num = rom.rom_size / 0x4000;
-        assert!(rom_bank_num.is_power_of_two());
-        let ram_bank_num = rom.ram_size / 0x2000;
-        assert!(rom.ram_size == 0 || ram_bank_num.is_power_of_two());
-        Self {
+    pub fn new(rom: &Rom, internal_ram: Option<Vec<u8>>) -> Result<Self, Error> {
+        let rom_bank_num = rom_bank_num(rom, 0x4000);
+        let ram_bank_num = ram_bank_num(rom, 0x2000)?;
+        let mbc30 = rom.rom_size > 2 * 1024 * 1024 || rom.ram_size > 32 * 1024;
+        if mbc30 {
+            info!("MBC3: MBC30 mode");
+        }
+        let mut ret = Self {
             rom_bank: 1,
             rom_bank_mask: rom_bank_num.saturating_sub(1) as u8,
             ram_bank_or_timer: RamBankOrTimer::Invalid,
             ram_bank_mask: ram_bank_num.saturating_sub(1) as u8,
             ram_and_timer_enable: false,
-            clock_data_latch: Utc::now(),
+            rtc: Rtc::default(),
+            rtc_latch: [0; 5],
             clock_data_req: !0,
-            day_counter_carry: false,
+            mbc30,
+            has_timer: rom.cartridge_type.has_timer,
+        };
+        if let Some(data) = internal_ram {
+            match data.len() {
+                RTC_SAVE_SIZE | RTC_SAVE_SIZE_32BIT => ret.load_rtc(&data),
+                0 => {}
+                len => warn!("MBC3: Invalid RTC data size: {len}"),
+            }
+        }
+        Ok(ret)
+    }
+
+    fn load_rtc(&mut self, data: &[u8]) {
+        let u32_at = |i: usize| u32::from_le_bytes(data[i * 4..i * 4 + 4].try_into().unwrap());
+        for i in 0..5 {
+            self.rtc.write(i as u8 + 8, u32_at(i) as u8);
+            self.rtc_latch[i] = u32_at(i + 5) as u8;
+        }
+        let timestamp = if data.len() == RTC_SAVE_SIZE {
+            i64::from_le_bytes(data[40..48].try_into().unwrap())
+        } else {
+            u32_at(10) as i64
+        };
+        self.rtc.host_time = HostTime::new(timestamp);
+    }
+
+    fn save_rtc(&self) -> Vec<u8> {
+        let mut ret = Vec::with_capacity(RTC_SAVE_SIZE);
+        for reg in self.rtc.registers().into_iter().chain(self.rtc_latch) {
+            ret.extend_from_slice(&(reg as u32).to_le_bytes());
+        }
+        ret.extend_from_slice(&self.rtc.host_time.now().to_le_bytes());
+        ret
+    }
+}
+
+impl Rtc {
+    fn registers(&self) -> [u8; 5] {
+        [
+            self.seconds,
+            self.minutes,
+            self.hours,
+            self.days as u8,
+            pack! {
+                0..=0 => (self.days >> 8) as u8,
+                6 => self.halt,
+                7 => self.day_counter_carry,
+            },
+        ]
+    }
+
+    fn write(&mut self, ix: u8, data: u8) {
+        match ix {
+            0x8 => {
+                self.seconds = data & 0x3F;
+                // Writing seconds resets the sub-second divider
+                self.sub_second = 0;
+            }
+            0x9 => self.minutes = data & 0x3F,
+            0xA => self.hours = data & 0x1F,
+            0xB => self.days = (self.days & 0x100) | data as u16,
+            0xC => {
+                self.days = (self.days & 0xFF) | ((data as u16 & 1) << 8);
+                self.halt = data & 0x40 != 0;
+                self.day_counter_carry = data & 0x80 != 0;
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn tick_second(&mut self) {
+        // Counters are compared for equality, so out of range values wrap around at their bit width
+        self.seconds = (self.seconds + 1) & 0x3F;
+        if self.seconds != 60 {
+            return;
+        }
+        self.seconds = 0;
+        self.minutes = (self.minutes + 1) & 0x3F;
+        if self.minutes != 60 {
+            return;
+        }
+        self.minutes = 0;
+        self.hours = (self.hours + 1) & 0x1F;
+        if self.hours != 24 {
+            return;
+        }
+        self.hours = 0;
+        self.days += 1;
+        if self.days == 512 {
+            self.days = 0;
+            self.day_counter_carry = true;
+        }
+    }
+
+    fn advance(&mut self, mut seconds: u64) {
+        if self.halt {
+            return;
+        }
+        while seconds > 0 && !(self.seconds < 60 && self.minutes < 60 && self.hours < 24) {
+            self.tick_second();
+            seconds -= 1;
+        }
+
+        let total =
+            self.seconds as u64 + self.minutes as u64 * 60 + self.hours as u64 * 3600 + seconds;
+        self.seconds = (total % 60) as u8;
+        self.minutes = (total / 60 % 60) as u8;
+        self.hours = (total / 3600 % 24) as u8;
+        let days = self.days as u64 + total / 86400;
+        if days >= 512 {
+            self.day_counter_carry = true;
+        }
+        self.days = (days % 512) as u16;
+    }
+
+    fn tick(&mut self, dots: u64) {
+        self.host_time.tick(dots);
+        if self.halt {
+            return;
+        }
+        self.sub_second += dots;
+        if self.sub_second >= DOTS_PER_SECOND {
+            self.sub_second -= DOTS_PER_SECOND;
+            self.tick_second();
         }
     }
 }
@@ -59,19 +211,9 @@ impl super::MbcTrait for Mbc3 {
                             let offset = (ram_bank & self.ram_bank_mask) as usize * 0x2000;
                             ctx.external_ram()[offset + (addr & 0x1FFF) as usize]
                         }
-                        RamBankOrTimer::Timer(ix) => match ix {
-                            0x8 => self.clock_data_latch.second() as u8,
-                            0x9 => self.clock_data_latch.minute() as u8,
-                            0xA => self.clock_data_latch.hour() as u8,
-                            0xB => (self.clock_data_latch.num_days_from_ce() & 0xff) as u8,
-                            0xC => pack! {
-                                0..=0 => (self.clock_data_latch.num_days_from_ce() >> 8) & 1,
-                                1..=5 => !0,
-                                6 => false,
-                                7 => self.day_counter_carry,
-                            },
-                            _ => unreachable!(),
-                        },
+                        // Unused bits of day counter high read as 1
+                        RamBankOrTimer::Timer(0xC) => self.rtc_latch[4] | 0x3E,
+                        RamBankOrTimer::Timer(ix) => self.rtc_latch[(ix - 8) as usize],
                         RamBankOrTimer::Invalid => !0,
                     }
                 } else {
@@ -82,12 +224,24 @@ impl super::MbcTrait for Mbc3 {
         }
     }
 
+    fn rom_bank(&self, addr: u16) -> u16 {
+        if addr < 0x4000 {
+            0
+        } else {
+            (self.rom_bank & self.rom_bank_mask) as u16
+        }
+    }
+
     fn write(&mut self, ctx: &mut impl Context, addr: u16, data: u8) {
         match addr {
             0x0000..=0x1FFF => self.ram_and_timer_enable = data & 0x0F == 0x0A,
-            0x2000..=0x3FFF => self.rom_bank = max(1, data & 0x7F),
+            0x2000..=0x3FFF => {
+                let mask = if self.mbc30 { 0xFF } else { 0x7F };
+                self.rom_bank = max(1, data & mask);
+            }
             0x4000..=0x5FFF => {
-                if data <= 3 {
+                let max_ram_bank = if self.mbc30 { 7 } else { 3 };
+                if data <= max_ram_bank {
                     self.ram_bank_or_timer = RamBankOrTimer::RamBank(data);
                 } else if (8..=0xC).contains(&data) {
                     self.ram_bank_or_timer = RamBankOrTimer::Timer(data);
@@ -102,15 +256,7 @@ impl super::MbcTrait for Mbc3 {
                 }
 
                 if self.clock_data_req == 0 && data == 1 {
-                    let now = Utc::now();
-
-                    if self.clock_data_latch.num_days_from_ce() & 0x1FF
-                        > now.num_days_from_ce() & 0x1FF
-                    {
-                        self.day_counter_carry = true;
-                    }
-
-                    self.clock_data_latch = now;
+                    self.rtc_latch = self.rtc.registers();
                 }
                 self.clock_data_req = data;
             }
@@ -121,7 +267,12 @@ impl super::MbcTrait for Mbc3 {
                             let offset = (ram_bank & self.ram_bank_mask) as usize * 0x2000;
                             ctx.external_ram_mut()[offset + (addr & 0x1FFF) as usize] = data;
                         }
-                        RamBankOrTimer::Timer(_) => {}
+                        RamBankOrTimer::Timer(ix) => {
+                            let i = (ix - 8) as usize;
+                            self.rtc.write(ix, data);
+                            // Written values are visible without latching again
+                            self.rtc_latch[i] = self.rtc.registers()[i];
+                        }
                         RamBankOrTimer::Invalid => {}
                     }
                 }
@@ -129,4 +280,19 @@ impl super::MbcTrait for Mbc3 {
             _ => unreachable!(),
         }
     }
+
+    fn tick(&mut self, _ctx: &mut impl Context, dots: u64) {
+        self.rtc.tick(dots);
+    }
+
+    fn internal_ram(&self) -> Option<Vec<u8>> {
+        self.has_timer.then(|| self.save_rtc())
+    }
+
+    fn sync_rtc(&mut self, now: i64, advance: bool) {
+        let elapsed = self.rtc.host_time.sync(now);
+        if advance {
+            self.rtc.advance(elapsed);
+        }
+    }
 }
@@ -2,6 +2,12 @@ use bitvec::prelude::*;
 use log::warn;
 use serde::{Deserialize, Serialize};
 
+use crate::{
+    gameboy::Error,
+    interface::Rumble,
+    mbc::{check_internal_ram, ram_bank_num, rom_bank_num},
+};
+
 #[derive(Serialize, Deserialize)]
 pub struct Mbc5 {
     rom_bank: u16,
@@ -9,22 +15,29 @@ pub struct Mbc5 {
     ram_bank: u8,
     ram_bank_mask: u8,
     ram_enable: bool,
+    has_rumble: bool,
+    rumble: bool,
+    // Dots elapsed and dots the motor was on since the last `take_rumble`
+    rumble_total_dots: u64,
+    rumble_on_dots: u64,
 }
 
 impl Mbc5 {
-    pub fn new(rom: &crate::rom::Rom, internal_ram: Option<Vec<u8>>) -> Self {
-        assert!(internal_ram.is_none());
-        let rom_bank_num = rom.rom_size / 0x4000;
-        assert!(rom_bank_num.is_power_of_two());
-        let ram_bank_num = rom.ram_size / 0x2000;
-        assert!(rom.ram_size == 0 || ram_bank_num.is_power_of_two());
-        Self {
+    pub fn new(rom: &crate::rom::Rom, internal_ram: Option<Vec<u8>>) -> Result<Self, Error> {
+        check_internal_ram(&internal_ram, 0)?;
+        let rom_bank_num = rom_bank_num(rom, 0x4000);
+        let ram_bank_num = ram_bank_num(rom, 0x2000)?;
+        Ok(Self {
             rom_bank: 1,
             rom_bank_mask: rom_bank_num.saturating_sub(1) as u16,
             ram_bank: 0,
             ram_bank_mask: ram_bank_num.saturating_sub(1) as u8,
             ram_enable: false,
-        }
+            has_rumble: rom.cartridge_type.has_rumble,
+            rumble: false,
+            rumble_total_dots: 0,
+            rumble_on_dots: 0,
+        })
     }
 }
 
@@ -48,14 +61,23 @@ impl super::MbcTrait for Mbc5 {
         }
     }
 
+    fn rom_bank(&self, addr: u16) -> u16 {
+        if addr < 0x4000 {
+            0
+        } else {
+            self.rom_bank & self.rom_bank_mask
+        }
+    }
+
     fn write(&mut self, ctx: &mut impl super::Context, addr: u16, data: u8) {
         match addr {
             0x0000..=0x1FFF => self.ram_enable = data & 0x0F == 0x0A,
             0x2000..=0x2FFF => self.rom_bank.view_bits_mut::<Lsb0>()[0..=7].store(data),
             0x3000..=0x3FFF => self.rom_bank.view_bits_mut::<Lsb0>()[8..=8].store(data & 1),
             0x4000..=0x5FFF => {
-                if ctx.rom().cartridge_type.has_rumble {
-                    // TODO: bit 3 is runble enable
+                if self.has_rumble {
+                    // Bit 3 drives the rumble motor
+                    self.rumble = data & 0x08 != 0;
                     self.ram_bank = data & 0x07
                 } else {
                     self.ram_bank = data & 0x0F
@@ -70,4 +92,30 @@ impl super::MbcTrait for Mbc5 {
             _ => warn!("Write invalid MBC5 Register: ${addr:04X} = ${data:02X}"),
         }
     }
+
+    fn tick(&mut self, _ctx: &mut impl super::Context, dots: u64) {
+        if self.has_rumble {
+            self.rumble_total_dots += dots;
+            if self.rumble {
+                self.rumble_on_dots += dots;
+            }
+        }
+    }
+
+    fn take_rumble(&mut self) -> Option<Rumble> {
+        if !self.has_rumble {
+            return None;
+        }
+        let intensity = if self.rumble_total_dots == 0 {
+            self.rumble as u8 as f32
+        } else {
+            self.rumble_on_dots as f32 / self.rumble_total_dots as f32
+        };
+        self.rumble_total_dots = 0;
+        self.rumble_on_dots = 0;
+        Some(Rumble {
+            active: self.rumble,
+            intensity,
+        })
+    }
 }
@@ -0,0 +1,204 @@
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    gameboy::Error,
+    mbc::{check_internal_ram, ram_bank_num, rom_bank_num, Context},
+    rom::Rom,
+};
+
+pub const FLASH_SIZE: usize = 1024 * 1024;
+const FLASH_SECTOR_SIZE: usize = 128 * 1024;
+const FLASH_PAGE_SIZE: usize = 128;
+
+#[derive(Serialize, Deserialize)]
+pub struct Mbc6 {
+    rom_bank: [u8; 2],
+    rom_bank_mask: u8,
+    flash_select: [bool; 2],
+    ram_bank: [u8; 2],
+    ram_bank_mask: u8,
+    ram_enable: bool,
+    flash: Flash,
+}
+
+/// Macronix MX29F008TC 1MiB flash memory
+#[derive(Serialize, Deserialize)]
+struct Flash {
+    #[serde(with = "serde_bytes")]
+    data: Vec<u8>,
+    enable: bool,
+    write_enable: bool,
+    state: FlashState,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+enum FlashState {
+    Read,
+    Unlock1,
+    Unlock2,
+    EraseSetup,
+    EraseUnlock1,
+    EraseUnlock2,
+    Program,
+    Id,
+}
+
+impl Mbc6 {
+    pub fn new(rom: &Rom, internal_ram: Option<Vec<u8>>) -> Result<Self, Error> {
+        check_internal_ram(&internal_ram, FLASH_SIZE)?;
+        let rom_bank_num = rom_bank_num(rom, 0x2000);
+        let ram_bank_num = ram_bank_num(rom, 0x1000)?;
+        Ok(Self {
+            rom_bank: [0; 2],
+            rom_bank_mask: rom_bank_num.saturating_sub(1) as u8,
+            flash_select: [false; 2],
+            ram_bank: [0; 2],
+            ram_bank_mask: ram_bank_num.saturating_sub(1) as u8,
+            ram_enable: false,
+            flash: Flash {
+                data: internal_ram.unwrap_or_else(|| vec![!0; FLASH_SIZE]),
+                enable: false,
+                write_enable: false,
+                state: FlashState::Read,
+            },
+        })
+    }
+
+    fn flash_addr(&self, addr: u16) -> usize {
+        let i = ((addr >> 13) & 1) as usize;
+        let bank = (self.rom_bank[i] as usize) % (FLASH_SIZE / 0x2000);
+        bank * 0x2000 + (addr & 0x1FFF) as usize
+    }
+}
+
+impl Flash {
+    fn read(&self, addr: usize) -> u8 {
+        if !self.enable {
+            return !0;
+        }
+        match self.state {
+            // Manufacturer ID and device ID
+            FlashState::Id => match addr & 0xFF {
+                0x00 => 0xC2,
+                0x01 => 0x81,
+                _ => 0x00,
+            },
+            _ => self.data[addr],
+        }
+    }
+
+    fn write(&mut self, addr: usize, data: u8) {
+        if !self.enable || !self.write_enable {
+            warn!("MBC6: Flash write while disabled: ${addr:05X} = ${data:02X}");
+            return;
+        }
+
+        // Command addresses are decoded with 15 bits of address
+        let cmd_addr = addr & 0x7FFF;
+
+        use FlashState::*;
+        self.state = match (self.state, cmd_addr, data) {
+            (Program, _, _) => {
+                // Programming can only clear bits; ends at the last byte of the page
+                self.data[addr] &= data;
+                if addr % FLASH_PAGE_SIZE == FLASH_PAGE_SIZE - 1 {
+                    Read
+                } else {
+                    Program
+                }
+            }
+            (_, _, 0xF0) => Read,
+            (Read | Id, 0x5555, 0xAA) => Unlock1,
+            (Unlock1, 0x2AAA, 0x55) => Unlock2,
+            (Unlock2, 0x5555, 0x90) => Id,
+            (Unlock2, 0x5555, 0xA0) => Program,
+            (Unlock2, 0x5555, 0x80) => EraseSetup,
+            (EraseSetup, 0x5555, 0xAA) => EraseUnlock1,
+            (EraseUnlock1, 0x2AAA, 0x55) => EraseUnlock2,
+            (EraseUnlock2, 0x5555, 0x10) => {
+                debug!("MBC6: Flash chip erase");
+                self.data.fill(!0);
+                Read
+            }
+            (EraseUnlock2, _, 0x30) => {
+                let start = addr / FLASH_SECTOR_SIZE * FLASH_SECTOR_SIZE;
+                debug!("MBC6: Flash sector erase: ${start:05X}");
+                self.data[start..start + FLASH_SECTOR_SIZE].fill(!0);
+                Read
+            }
+            (state, _, _) => {
+                warn!("MBC6: Invalid flash command in {state:?}: ${addr:05X} = ${data:02X}");
+                Read
+            }
+        };
+    }
+}
+
+impl super::MbcTrait for Mbc6 {
+    fn read(&mut self, ctx: &mut impl Context, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3FFF => ctx.rom().data[addr as usize],
+            0x4000..=0x7FFF => {
+                let i = ((addr >> 13) & 1) as usize;
+                if self.flash_select[i] {
+                    self.flash.read(self.flash_addr(addr))
+                } else {
+                    let offset = (self.rom_bank[i] & self.rom_bank_mask) as usize * 0x2000;
+                    ctx.rom().data[offset + (addr & 0x1FFF) as usize]
+                }
+            }
+            0xA000..=0xBFFF => {
+                if self.ram_enable && !ctx.external_ram().is_empty() {
+                    let i = ((addr >> 12) & 1) as usize;
+                    let offset = (self.ram_bank[i] & self.ram_bank_mask) as usize * 0x1000;
+                    ctx.external_ram()[offset + (addr & 0x0FFF) as usize]
+                } else {
+                    !0
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn rom_bank(&self, addr: u16) -> u16 {
+        // In 8KiB banks
+        match addr {
+            0x0000..=0x3FFF => addr >> 13,
+            _ => (self.rom_bank[((addr >> 13) & 1) as usize] & self.rom_bank_mask) as u16,
+        }
+    }
+
+    fn write(&mut self, ctx: &mut impl Context, addr: u16, data: u8) {
+        match addr {
+            0x0000..=0x03FF => self.ram_enable = data & 0x0F == 0x0A,
+            0x0400..=0x07FF => self.ram_bank[0] = data,
+            0x0800..=0x0BFF => self.ram_bank[1] = data,
+            0x0C00..=0x0FFF => self.flash.enable = data & 1 != 0,
+            0x1000 => self.flash.write_enable = data & 1 != 0,
+            0x2000..=0x27FF => self.rom_bank[0] = data,
+            0x2800..=0x2FFF => self.flash_select[0] = data == 0x08,
+            0x3000..=0x37FF => self.rom_bank[1] = data,
+            0x3800..=0x3FFF => self.flash_select[1] = data == 0x08,
+            0x4000..=0x7FFF => {
+                let i = ((addr >> 13) & 1) as usize;
+                if self.flash_select[i] {
+                    let flash_addr = self.flash_addr(addr);
+                    self.flash.write(flash_addr, data);
+                }
+            }
+            0xA000..=0xBFFF => {
+                if self.ram_enable && !ctx.external_ram().is_empty() {
+                    let i = ((addr >> 12) & 1) as usize;
+                    let offset = (self.ram_bank[i] & self.ram_bank_mask) as usize * 0x1000;
+                    ctx.external_ram_mut()[offset + (addr & 0x0FFF) as usize] = data;
+                }
+            }
+            _ => warn!("MBC6: Write ${addr:04X} = ${data:02X}"),
+        }
+    }
+
+    fn internal_ram(&self) -> Option<Vec<u8>> {
+        Some(self.flash.data.clone())
+    }
+}
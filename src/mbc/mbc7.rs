@@ -0,0 +1,330 @@
+use bitvec::prelude::*;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    gameboy::Error,
+    io::Input,
+    mbc::{check_internal_ram, rom_bank_num, Context},
+    rom::Rom,
+    util::pack,
+};
+
+pub const EEPROM_SIZE: usize = 256;
+
+const ACCEL_CENTER: f32 = 0x81D0 as f32;
+const ACCEL_GRAVITY: f32 = 0x70 as f32;
+
+#[derive(Serialize, Deserialize)]
+pub struct Mbc7 {
+    rom_bank: u8,
+    rom_bank_mask: u8,
+    ram_enable: [bool; 2],
+    tilt: [f32; 2],
+    accel_latch: [u16; 2],
+    accel_erased: bool,
+    eeprom: Eeprom,
+}
+
+/// Microchip 93LC56 serial EEPROM in 16-bit organization
+#[derive(Serialize, Deserialize)]
+struct Eeprom {
+    #[serde(with = "serde_bytes")]
+    data: Vec<u8>,
+    cs: bool,
+    clk: bool,
+    di: bool,
+    do_: bool,
+    write_enable: bool,
+    state: EepromState,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+enum EepromState {
+    Idle,
+    Command {
+        bits: u16,
+        count: usize,
+    },
+    Read {
+        addr: u8,
+        data: u16,
+        count: usize,
+    },
+    Write {
+        addr: Option<u8>,
+        bits: u16,
+        count: usize,
+    },
+    Done,
+}
+
+impl Mbc7 {
+    pub fn new(rom: &Rom, internal_ram: Option<Vec<u8>>) -> Result<Self, Error> {
+        check_internal_ram(&internal_ram, EEPROM_SIZE)?;
+        let rom_bank_num = rom_bank_num(rom, 0x4000);
+        Ok(Self {
+            rom_bank: 1,
+            rom_bank_mask: rom_bank_num.saturating_sub(1) as u8,
+            ram_enable: [false; 2],
+            tilt: [0.0; 2],
+            accel_latch: [0x8000; 2],
+            accel_erased: false,
+            eeprom: Eeprom {
+                data: internal_ram.unwrap_or_else(|| vec![!0; EEPROM_SIZE]),
+                cs: false,
+                clk: false,
+                di: false,
+                do_: true,
+                write_enable: false,
+                state: EepromState::Idle,
+            },
+        })
+    }
+}
+
+impl Eeprom {
+    fn word(&self, addr: u8) -> u16 {
+        let i = (addr & 0x7F) as usize * 2;
+        u16::from_le_bytes([self.data[i], self.data[i + 1]])
+    }
+
+    fn set_word(&mut self, addr: u8, data: u16) {
+        let i = (addr & 0x7F) as usize * 2;
+        self.data[i..i + 2].copy_from_slice(&data.to_le_bytes());
+    }
+
+    fn read(&self) -> u8 {
+        pack! {
+            7 => self.cs,
+            6 => self.clk,
+            2..=5 => !0,
+            1 => self.di,
+            0 => self.do_,
+        }
+    }
+
+    fn write(&mut self, data: u8) {
+        let v = data.view_bits::<Lsb0>();
+        let (cs, clk, di) = (v[7], v[6], v[1]);
+
+        if !cs {
+            self.state = EepromState::Idle;
+            self.do_ = true;
+        } else if clk && !self.clk {
+            self.clock(di);
+        }
+
+        self.cs = cs;
+        self.clk = clk;
+        self.di = di;
+    }
+
+    fn clock(&mut self, di: bool) {
+        use EepromState::*;
+        self.state = match self.state {
+            // Wait for the start bit
+            Idle => {
+                if di {
+                    Command { bits: 0, count: 0 }
+                } else {
+                    Idle
+                }
+            }
+            Command { bits, count } => {
+                let bits = bits << 1 | di as u16;
+                if count + 1 < 10 {
+                    Command {
+                        bits,
+                        count: count + 1,
+                    }
+                } else {
+                    self.command(bits)
+                }
+            }
+            Read { addr, data, count } => {
+                if count == 16 {
+                    // Sequential read continues to the next word
+                    let addr = addr.wrapping_add(1) & 0x7F;
+                    let data = self.word(addr);
+                    self.do_ = data & 0x8000 != 0;
+                    Read {
+                        addr,
+                        data: data << 1,
+                        count: 1,
+                    }
+                } else {
+                    self.do_ = data & 0x8000 != 0;
+                    Read {
+                        addr,
+                        data: data << 1,
+                        count: count + 1,
+                    }
+                }
+            }
+            Write { addr, bits, count } => {
+                let bits = bits << 1 | di as u16;
+                if count + 1 < 16 {
+                    Write {
+                        addr,
+                        bits,
+                        count: count + 1,
+                    }
+                } else {
+                    if self.write_enable {
+                        match addr {
+                            Some(addr) => self.set_word(addr, bits),
+                            None => {
+                                for addr in 0..(EEPROM_SIZE / 2) as u8 {
+                                    self.set_word(addr, bits);
+                                }
+                            }
+                        }
+                    }
+                    // Writing completes immediately, so report ready
+                    self.do_ = true;
+                    Done
+                }
+            }
+            Done => Done,
+        };
+    }
+
+    fn command(&mut self, bits: u16) -> EepromState {
+        let opcode = bits >> 8;
+        let addr = (bits & 0x7F) as u8;
+        match opcode {
+            // READ
+            0b10 => {
+                debug!("MBC7: EEPROM READ ${addr:02X}");
+                // A dummy zero bit precedes data
+                self.do_ = false;
+                EepromState::Read {
+                    addr,
+                    data: self.word(addr),
+                    count: 0,
+                }
+            }
+            // WRITE
+            0b01 => {
+                debug!("MBC7: EEPROM WRITE ${addr:02X}");
+                EepromState::Write {
+                    addr: Some(addr),
+                    bits: 0,
+                    count: 0,
+                }
+            }
+            // ERASE
+            0b11 => {
+                debug!("MBC7: EEPROM ERASE ${addr:02X}");
+                if self.write_enable {
+                    self.set_word(addr, !0);
+                }
+                self.do_ = true;
+                EepromState::Done
+            }
+            _ => match (bits >> 6) & 3 {
+                // EWDS
+                0b00 => {
+                    debug!("MBC7: EEPROM EWDS");
+                    self.write_enable = false;
+                    EepromState::Done
+                }
+                // WRAL
+                0b01 => {
+                    debug!("MBC7: EEPROM WRAL");
+                    EepromState::Write {
+                        addr: None,
+                        bits: 0,
+                        count: 0,
+                    }
+                }
+                // ERAL
+                0b10 => {
+                    debug!("MBC7: EEPROM ERAL");
+                    if self.write_enable {
+                        self.data.fill(!0);
+                    }
+                    self.do_ = true;
+                    EepromState::Done
+                }
+                // EWEN
+                0b11 => {
+                    debug!("MBC7: EEPROM EWEN");
+                    self.write_enable = true;
+                    EepromState::Done
+                }
+                _ => unreachable!(),
+            },
+        }
+    }
+}
+
+impl super::MbcTrait for Mbc7 {
+    fn read(&mut self, ctx: &mut impl Context, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3FFF => ctx.rom().data[addr as usize],
+            0x4000..=0x7FFF => {
+                let offset = (self.rom_bank & self.rom_bank_mask) as usize * 0x4000;
+                ctx.rom().data[offset + (addr & 0x3FFF) as usize]
+            }
+            0xA000..=0xAFFF if self.ram_enable == [true; 2] => match (addr >> 4) & 0xF {
+                0x2 => self.accel_latch[0] as u8,
+                0x3 => (self.accel_latch[0] >> 8) as u8,
+                0x4 => self.accel_latch[1] as u8,
+                0x5 => (self.accel_latch[1] >> 8) as u8,
+                0x6 => 0x00,
+                0x8 => self.eeprom.read(),
+                _ => !0,
+            },
+            0xA000..=0xBFFF => !0,
+            _ => unreachable!(),
+        }
+    }
+
+    fn rom_bank(&self, addr: u16) -> u16 {
+        if addr < 0x4000 {
+            0
+        } else {
+            (self.rom_bank & self.rom_bank_mask) as u16
+        }
+    }
+
+    fn write(&mut self, _ctx: &mut impl Context, addr: u16, data: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.ram_enable[0] = data == 0x0A,
+            0x2000..=0x3FFF => self.rom_bank = data,
+            0x4000..=0x5FFF => self.ram_enable[1] = data == 0x40,
+            0x6000..=0x7FFF => {}
+            0xA000..=0xAFFF if self.ram_enable == [true; 2] => match (addr >> 4) & 0xF {
+                0x0 => {
+                    if data == 0x55 {
+                        self.accel_latch = [0x8000; 2];
+                        self.accel_erased = true;
+                    }
+                }
+                0x1 => {
+                    if data == 0xAA && self.accel_erased {
+                        for i in 0..2 {
+                            let v = ACCEL_CENTER + self.tilt[i].clamp(-1.0, 1.0) * ACCEL_GRAVITY;
+                            self.accel_latch[i] = v as u16;
+                        }
+                        self.accel_erased = false;
+                    }
+                }
+                0x8 => self.eeprom.write(data),
+                _ => warn!("MBC7: Write ${addr:04X} = ${data:02X}"),
+            },
+            0xA000..=0xBFFF => {}
+            _ => unreachable!(),
+        }
+    }
+
+    fn internal_ram(&self) -> Option<Vec<u8>> {
+        Some(self.eeprom.data.clone())
+    }
+
+    fn set_input(&mut self, input: &Input) {
+        self.tilt = [input.tilt_x, input.tilt_y];
+    }
+}
@@ -0,0 +1,158 @@
+use bitvec::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    gameboy::Error,
+    mbc::{check_internal_ram, ram_bank_num, rom_bank_num, Context},
+    rom::Rom,
+};
+
+#[derive(Serialize, Deserialize)]
+pub struct Mmm01 {
+    mapped: bool,
+    rom_bank_low: u8,
+    rom_bank_mid: u8,
+    rom_bank_high: u8,
+    rom_bank_mask: u8,
+    ram_bank_low: u8,
+    ram_bank_high: u8,
+    ram_bank_mask: u8,
+    ram_enable: bool,
+    mbc1_mode: bool,
+    mbc1_mode_lock: bool,
+    rom_bank_num_mask: u16,
+    ram_bank_num_mask: u8,
+}
+
+impl Mmm01 {
+    pub fn new(rom: &Rom, internal_ram: Option<Vec<u8>>) -> Result<Self, Error> {
+        check_internal_ram(&internal_ram, 0)?;
+        let rom_bank_num = rom_bank_num(rom, 0x4000);
+        let ram_bank_num = ram_bank_num(rom, 0x2000)?;
+        Ok(Self {
+            mapped: false,
+            rom_bank_low: 0,
+            rom_bank_mid: 0,
+            rom_bank_high: 0,
+            rom_bank_mask: 0,
+            ram_bank_low: 0,
+            ram_bank_high: 0,
+            ram_bank_mask: 0,
+            ram_enable: false,
+            mbc1_mode: false,
+            mbc1_mode_lock: false,
+            rom_bank_num_mask: rom_bank_num.saturating_sub(1) as u16,
+            ram_bank_num_mask: ram_bank_num.saturating_sub(1) as u8,
+        })
+    }
+
+    fn rom_bank(&self, upper: bool) -> u16 {
+        if !self.mapped {
+            // Before mapping, RA22-15 are forced high, so the menu in the last 32KiB is visible
+            return (if upper { 0x1FF } else { 0x1FE }) & self.rom_bank_num_mask;
+        }
+
+        // Masked bits of RA18-15 are fixed to select the game's slice of the ROM
+        let fixed = self.rom_bank_mask << 1;
+        let low = if upper {
+            if self.rom_bank_low & !fixed == 0 {
+                self.rom_bank_low | 1
+            } else {
+                self.rom_bank_low
+            }
+        } else {
+            self.rom_bank_low & fixed
+        };
+
+        let bank = (self.rom_bank_high as u16) << 7 | (self.rom_bank_mid as u16) << 5 | low as u16;
+        bank & self.rom_bank_num_mask
+    }
+
+    fn ram_bank(&self) -> u8 {
+        let low = if self.mbc1_mode { self.ram_bank_low } else { 0 };
+        (self.ram_bank_high << 2 | low) & self.ram_bank_num_mask
+    }
+}
+
+impl super::MbcTrait for Mmm01 {
+    fn read(&mut self, ctx: &mut impl Context, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3FFF => {
+                let offset = self.rom_bank(false) as usize * 0x4000;
+                ctx.rom().data[offset + addr as usize]
+            }
+            0x4000..=0x7FFF => {
+                let offset = self.rom_bank(true) as usize * 0x4000;
+                ctx.rom().data[offset + (addr & 0x3FFF) as usize]
+            }
+            0xA000..=0xBFFF => {
+                if self.ram_enable && !ctx.external_ram().is_empty() {
+                    let offset = self.ram_bank() as usize * 0x2000;
+                    ctx.external_ram()[offset + (addr & 0x1FFF) as usize]
+                } else {
+                    !0
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn rom_bank(&self, addr: u16) -> u16 {
+        Mmm01::rom_bank(self, addr >= 0x4000)
+    }
+
+    fn write(&mut self, ctx: &mut impl Context, addr: u16, data: u8) {
+        let v = data.view_bits::<Lsb0>();
+        match addr {
+            0x0000..=0x1FFF => {
+                log::debug!("MMM01: RAM enable: {data:02X}");
+                self.ram_enable = data & 0x0F == 0x0A;
+                if !self.mapped {
+                    self.ram_bank_mask = v[4..=5].load();
+                    if v[6] {
+                        log::info!("MMM01: Mapping locked");
+                        self.mapped = true;
+                    }
+                }
+            }
+            0x2000..=0x3FFF => {
+                log::debug!("MMM01: ROM bank: {data:02X}");
+                let fixed = if self.mapped {
+                    self.rom_bank_mask << 1
+                } else {
+                    0
+                };
+                self.rom_bank_low = (self.rom_bank_low & fixed) | (data & 0x1F & !fixed);
+                if !self.mapped {
+                    self.rom_bank_mid = v[5..=6].load();
+                }
+            }
+            0x4000..=0x5FFF => {
+                log::debug!("MMM01: RAM bank: {data:02X}");
+                let fixed = if self.mapped { self.ram_bank_mask } else { 0 };
+                self.ram_bank_low = (self.ram_bank_low & fixed) | (data & 0x03 & !fixed);
+                if !self.mapped {
+                    self.ram_bank_high = v[2..=3].load();
+                    self.rom_bank_high = v[4..=5].load();
+                    self.mbc1_mode_lock = v[6];
+                }
+            }
+            0x6000..=0x7FFF => {
+                log::debug!("MMM01: Mode: {data:02X}");
+                if !self.mbc1_mode_lock {
+                    self.mbc1_mode = v[0];
+                }
+                if !self.mapped {
+                    self.rom_bank_mask = v[2..=5].load();
+                }
+            }
+            0xA000..=0xBFFF => {
+                if self.ram_enable && !ctx.external_ram().is_empty() {
+                    let offset = self.ram_bank() as usize * 0x2000;
+                    ctx.external_ram_mut()[offset + (addr & 0x1FFF) as usize] = data;
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+}
@@ -1,17 +1,27 @@
+mod camera;
+mod huc1;
+mod huc3;
 mod mbc1;
 mod mbc2;
 mod mbc3;
 mod mbc5;
+mod mbc6;
+mod mbc7;
+mod mmm01;
+mod tama5;
 
 use ambassador::{delegatable_trait, Delegate};
 use log::warn;
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    consts::DOTS_PER_SECOND,
     context,
     gameboy::Error,
+    interface::{Camera, Infrared, Rumble},
+    io::Input,
     rom::{self, Rom},
-    util::trait_alias,
+    util::{to_si_bytesize, trait_alias},
 };
 
 trait_alias!(pub trait Context = context::Rom + context::ExternalRam);
@@ -21,9 +31,92 @@ trait_alias!(pub trait Context = context::Rom + context::ExternalRam);
 pub trait MbcTrait {
     fn read(&mut self, ctx: &mut impl Context, addr: u16) -> u8;
     fn write(&mut self, ctx: &mut impl Context, addr: u16, data: u8) {}
-    fn internal_ram(&self) -> Option<&[u8]> {
+    /// ROM bank mapped at `addr` in $0000-$7FFF
+    fn rom_bank(&self, addr: u16) -> u16 {
+        addr >> 14
+    }
+    fn tick(&mut self, ctx: &mut impl Context, dots: u64) {}
+    fn internal_ram(&self) -> Option<Vec<u8>> {
+        None
+    }
+    fn set_input(&mut self, input: &Input) {}
+    fn set_infrared(&mut self, infrared: Option<Box<dyn Infrared + Send + Sync>>) {}
+    fn set_camera(&mut self, camera: Option<Box<dyn Camera + Send + Sync>>) {}
+    /// Returns the rumble motor state since the last call, if the cartridge has a motor
+    fn take_rumble(&mut self) -> Option<Rumble> {
         None
     }
+    /// Sets the host time, advancing the RTC by the time elapsed since its state was saved if `advance`
+    fn sync_rtc(&mut self, now: i64, advance: bool) {}
+}
+
+/// Host time corresponding to the state of a cartridge RTC
+///
+/// This is advanced by emulated time, so saved timestamps do not depend on the speed of emulation.
+#[derive(Default, Serialize, Deserialize)]
+struct HostTime {
+    base: Option<i64>,
+    dots: u64,
+}
+
+impl HostTime {
+    fn new(base: i64) -> Self {
+        Self {
+            base: Some(base),
+            dots: 0,
+        }
+    }
+
+    fn tick(&mut self, dots: u64) {
+        self.dots += dots;
+    }
+
+    fn now(&self) -> i64 {
+        self.base.unwrap_or_default() + (self.dots / DOTS_PER_SECOND) as i64
+    }
+
+    /// Sets the current host time and returns the seconds elapsed since the saved state
+    fn sync(&mut self, now: i64) -> u64 {
+        let elapsed = if self.base.is_some() {
+            (now - self.now()).max(0) as u64
+        } else {
+            0
+        };
+        *self = Self::new(now);
+        elapsed
+    }
+}
+
+/// IR LED and receiver mapped into the cartridge RAM area
+#[derive(Default, Serialize, Deserialize)]
+struct IrPort {
+    led: bool,
+    light: bool,
+    #[serde(skip)]
+    infrared: Option<Box<dyn Infrared + Send + Sync>>,
+}
+
+impl IrPort {
+    fn set_infrared(&mut self, infrared: Option<Box<dyn Infrared + Send + Sync>>) {
+        self.infrared = infrared;
+    }
+
+    fn read(&mut self) -> u8 {
+        if let Some(light) = self.infrared.as_mut().and_then(|r| r.try_recv()) {
+            self.light = light;
+        }
+        0xC0 | self.light as u8
+    }
+
+    fn write(&mut self, data: u8) {
+        let led = data & 1 != 0;
+        if self.led != led {
+            self.led = led;
+            if let Some(infrared) = &mut self.infrared {
+                infrared.send(led);
+            }
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -31,11 +124,12 @@ pub struct NullMbc {}
 
 impl NullMbc {
     fn new(rom: &Rom) -> Self {
-        assert_eq!(
-            rom.rom_size,
-            32 * 1024,
-            "ROM only cartridge should be 32KiB"
-        );
+        if rom.rom_size != 32 * 1024 {
+            warn!(
+                "ROM only cartridge should be 32KiB, but {}",
+                to_si_bytesize(rom.rom_size)
+            );
+        }
         Self {}
     }
 }
@@ -45,18 +139,69 @@ impl MbcTrait for NullMbc {
         match addr {
             0x0000..=0x3FFF => ctx.rom().data[addr as usize],
             0x4000..=0x7FFF => ctx.rom().data[addr as usize],
-            0xA000..=0xBFFF => ctx.external_ram()[addr as usize - 0xA000],
+            0xA000..=0xBFFF => {
+                let ram = ctx.external_ram();
+                ram.get(addr as usize - 0xA000).copied().unwrap_or(!0)
+            }
             _ => panic!("{addr:04X}"),
         }
     }
     fn write(&mut self, ctx: &mut impl Context, addr: u16, data: u8) {
         match addr {
-            0xA000..=0xBFFF => ctx.external_ram_mut()[addr as usize - 0xA000] = data,
+            0xA000..=0xBFFF => {
+                if let Some(r) = ctx.external_ram_mut().get_mut(addr as usize - 0xA000) {
+                    *r = data;
+                }
+            }
             _ => warn!("Invalid address write: ${addr:04X} = ${data:02X}"),
         }
     }
 }
 
+/// Size of the mapper's internal memory in backup data, if it is fixed
+pub fn internal_ram_size(rom: &Rom) -> Option<usize> {
+    match &rom.cartridge_type.mbc {
+        Some(rom::Mbc::Mbc2) => Some(mbc2::RAM_SIZE),
+        Some(rom::Mbc::Mbc6) => Some(mbc6::FLASH_SIZE),
+        Some(rom::Mbc::Mbc7) => Some(mbc7::EEPROM_SIZE),
+        Some(rom::Mbc::HuC3) => Some(huc3::RTC_SAVE_SIZE),
+        Some(rom::Mbc::Tama5) => Some(tama5::SAVE_SIZE),
+        _ => None,
+    }
+}
+
+/// Number of ROM banks, rounded up to a power of two so that bank numbers can be masked
+fn rom_bank_num(rom: &Rom, bank_size: usize) -> u64 {
+    (rom.data.len() / bank_size).next_power_of_two() as u64
+}
+
+/// Number of RAM banks, RAM size must be a power of two
+fn ram_bank_num(rom: &Rom, bank_size: u64) -> Result<u64, Error> {
+    if !(rom.ram_size == 0 || rom.ram_size.is_power_of_two()) {
+        Err(unsupported_memory_size(rom))?
+    }
+    Ok(rom.ram_size / bank_size)
+}
+
+fn unsupported_memory_size(rom: &Rom) -> Error {
+    Error::UnsupportedMemorySize {
+        cartridge: rom.cartridge_type.to_string(),
+        rom_size: rom.rom_size,
+        ram_size: rom.ram_size,
+    }
+}
+
+/// Checks that the size of internal memory in backup data is `size`
+fn check_internal_ram(internal_ram: &Option<Vec<u8>>, size: usize) -> Result<(), Error> {
+    match internal_ram {
+        Some(data) if data.len() != size => Err(Error::BackupSizeMismatch {
+            expected: size,
+            actual: data.len(),
+        }),
+        _ => Ok(()),
+    }
+}
+
 macro_rules! def_mbc {
     ($($id:ident => $ty:ty,)*) => {
         #[derive(Serialize, Deserialize, Delegate)]
@@ -73,8 +218,9 @@ macro_rules! def_mbc {
             Ok(match cart_type.mbc {
                 None => Mbc::NullMbc(NullMbc::new(rom)),
                 $(
-                    Some(rom::Mbc::$id) => Mbc::$id(<$ty>::new(rom, internal_ram)),
+                    Some(rom::Mbc::$id) => Mbc::$id(<$ty>::new(rom, internal_ram)?),
                 )*
+                #[allow(unreachable_patterns)]
                 Some(mbc) => Err(Error::UnsupportedMbc(mbc))?,
             })
         }
@@ -84,6 +230,13 @@ macro_rules! def_mbc {
 def_mbc! {
     Mbc1 => mbc1::Mbc1,
     Mbc2 => mbc2::Mbc2,
+    Mmm01 => mmm01::Mmm01,
     Mbc3 => mbc3::Mbc3,
     Mbc5 => mbc5::Mbc5,
+    Mbc6 => mbc6::Mbc6,
+    Mbc7 => mbc7::Mbc7,
+    HuC1 => huc1::HuC1,
+    HuC3 => huc3::HuC3,
+    PocketCamera => camera::PocketCamera,
+    Tama5 => tama5::Tama5,
 }
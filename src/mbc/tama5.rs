@@ -0,0 +1,269 @@
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    consts::DOTS_PER_SECOND,
+    gameboy::Error,
+    mbc::{check_internal_ram, rom_bank_num, Context, HostTime},
+    rom::Rom,
+};
+
+const RAM_SIZE: usize = 0x20;
+
+// RAM, second, minute, hour, day of week, day, month, year (u8), last RTC time (u64)
+pub const SAVE_SIZE: usize = RAM_SIZE + 7 + 8;
+
+/// Bandai TAMA5
+///
+/// Registers are accessed through a window: $A001 selects a register and
+/// $A000 reads or writes its 4-bit value.
+#[derive(Serialize, Deserialize)]
+pub struct Tama5 {
+    rom_bank_mask: u8,
+    reg_select: u8,
+    regs: [u8; 16],
+    output: u8,
+    #[serde(with = "serde_bytes")]
+    ram: Vec<u8>,
+    rtc: Rtc,
+}
+
+/// Toshiba TC8521 compatible clock
+///
+/// Only the time page is implemented; alarm and RAM pages are not used by games.
+#[derive(Default, Serialize, Deserialize)]
+struct Rtc {
+    second: u8,
+    minute: u8,
+    hour: u8,
+    day_of_week: u8,
+    day: u8,
+    month: u8,
+    year: u8,
+    counter: u64,
+    host_time: HostTime,
+}
+
+// Register numbers
+const REG_ROM_BANK_LOW: u8 = 0x0;
+const REG_ROM_BANK_HIGH: u8 = 0x1;
+const REG_DATA_LOW: u8 = 0x4;
+const REG_DATA_HIGH: u8 = 0x5;
+const REG_ADDR_HIGH: u8 = 0x6;
+const REG_ADDR_LOW: u8 = 0x7;
+const REG_READY: u8 = 0xA;
+const REG_OUTPUT_LOW: u8 = 0xC;
+const REG_OUTPUT_HIGH: u8 = 0xD;
+
+impl Tama5 {
+    pub fn new(rom: &Rom, internal_ram: Option<Vec<u8>>) -> Result<Self, Error> {
+        check_internal_ram(&internal_ram, SAVE_SIZE)?;
+        let rom_bank_num = rom_bank_num(rom, 0x4000);
+
+        let mut ram = vec![0; RAM_SIZE];
+        let mut rtc = Rtc {
+            day: 1,
+            month: 1,
+            ..Default::default()
+        };
+        if let Some(data) = internal_ram {
+            ram.copy_from_slice(&data[..RAM_SIZE]);
+            rtc.load(&data[RAM_SIZE..]);
+        }
+
+        Ok(Self {
+            rom_bank_mask: rom_bank_num.saturating_sub(1) as u8,
+            reg_select: 0,
+            regs: [0; 16],
+            output: 0,
+            ram,
+            rtc,
+        })
+    }
+
+    fn rom_bank(&self) -> u8 {
+        let bank =
+            (self.regs[REG_ROM_BANK_HIGH as usize] & 1) << 4 | self.regs[REG_ROM_BANK_LOW as usize];
+        bank & self.rom_bank_mask
+    }
+
+    fn execute(&mut self) {
+        let addr_high = self.regs[REG_ADDR_HIGH as usize];
+        let addr = (addr_high & 1) << 4 | self.regs[REG_ADDR_LOW as usize];
+        let data = self.regs[REG_DATA_HIGH as usize] << 4 | self.regs[REG_DATA_LOW as usize];
+        match addr_high >> 1 {
+            0 => self.ram[addr as usize] = data,
+            1 => self.output = self.ram[addr as usize],
+            2 => self.rtc.write(addr & 0xF, data & 0xF),
+            3 => self.output = self.rtc.read(addr & 0xF),
+            cmd => warn!("TAMA5: Unknown command: {cmd}, addr = ${addr:02X}, data = ${data:02X}"),
+        }
+    }
+}
+
+fn days_in_month(month: u8, year: u8) -> u8 {
+    match month {
+        2 if year & 3 == 0 => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+impl Rtc {
+    // Register number to (field, decimal digit)
+    fn field(&mut self, reg: u8) -> Option<(&mut u8, u8)> {
+        Some(match reg {
+            0x0 => (&mut self.second, 0),
+            0x1 => (&mut self.second, 1),
+            0x2 => (&mut self.minute, 0),
+            0x3 => (&mut self.minute, 1),
+            0x4 => (&mut self.hour, 0),
+            0x5 => (&mut self.hour, 1),
+            0x6 => (&mut self.day_of_week, 0),
+            0x7 => (&mut self.day, 0),
+            0x8 => (&mut self.day, 1),
+            0x9 => (&mut self.month, 0),
+            0xA => (&mut self.month, 1),
+            0xB => (&mut self.year, 0),
+            0xC => (&mut self.year, 1),
+            _ => None?,
+        })
+    }
+
+    fn read(&mut self, reg: u8) -> u8 {
+        match self.field(reg) {
+            Some((v, 0)) => *v % 10,
+            Some((v, _)) => *v / 10,
+            None => 0,
+        }
+    }
+
+    fn write(&mut self, reg: u8, data: u8) {
+        debug!("TAMA5: RTC write: {reg:X} = {data:X}");
+        match self.field(reg) {
+            Some((v, 0)) => *v = *v / 10 * 10 + data % 10,
+            Some((v, _)) => *v = data % 10 * 10 + *v % 10,
+            None => {}
+        }
+    }
+
+    fn advance_day(&mut self) {
+        self.day_of_week = (self.day_of_week + 1) % 7;
+        self.day += 1;
+        if self.day > days_in_month(self.month, self.year) {
+            self.day = 1;
+            self.month += 1;
+            if self.month > 12 {
+                self.month = 1;
+                self.year = (self.year + 1) % 100;
+            }
+        }
+    }
+
+    fn advance(&mut self, seconds: u64) {
+        let total =
+            self.second as u64 + self.minute as u64 * 60 + self.hour as u64 * 3600 + seconds;
+        for _ in 0..total / 86400 {
+            self.advance_day();
+        }
+        let total = total % 86400;
+        self.hour = (total / 3600) as u8;
+        self.minute = (total / 60 % 60) as u8;
+        self.second = (total % 60) as u8;
+    }
+
+    fn tick(&mut self, dots: u64) {
+        self.host_time.tick(dots);
+        self.counter += dots;
+        if self.counter >= DOTS_PER_SECOND {
+            self.counter -= DOTS_PER_SECOND;
+            self.advance(1);
+        }
+    }
+
+    fn load(&mut self, data: &[u8]) {
+        self.second = data[0] % 60;
+        self.minute = data[1] % 60;
+        self.hour = data[2] % 24;
+        self.day_of_week = data[3] % 7;
+        self.day = data[4].clamp(1, 31);
+        self.month = data[5].clamp(1, 12);
+        self.year = data[6] % 100;
+        self.host_time = HostTime::new(i64::from_le_bytes(data[7..15].try_into().unwrap()));
+    }
+
+    fn save(&self) -> Vec<u8> {
+        let mut ret = vec![
+            self.second,
+            self.minute,
+            self.hour,
+            self.day_of_week,
+            self.day,
+            self.month,
+            self.year,
+        ];
+        ret.extend_from_slice(&self.host_time.now().to_le_bytes());
+        ret
+    }
+}
+
+impl super::MbcTrait for Tama5 {
+    fn read(&mut self, ctx: &mut impl Context, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3FFF => ctx.rom().data[addr as usize],
+            0x4000..=0x7FFF => {
+                let offset = self.rom_bank() as usize * 0x4000;
+                ctx.rom().data[offset + (addr & 0x3FFF) as usize]
+            }
+            0xA000..=0xBFFF if addr & 1 == 0 => match self.reg_select {
+                // Always ready to accept commands
+                REG_READY => 0xF1,
+                REG_OUTPUT_LOW => 0xF0 | (self.output & 0xF),
+                REG_OUTPUT_HIGH => 0xF0 | (self.output >> 4),
+                _ => !0,
+            },
+            0xA000..=0xBFFF => !0,
+            _ => unreachable!(),
+        }
+    }
+
+    fn rom_bank(&self, addr: u16) -> u16 {
+        if addr < 0x4000 {
+            0
+        } else {
+            Tama5::rom_bank(self) as u16
+        }
+    }
+
+    fn write(&mut self, _ctx: &mut impl Context, addr: u16, data: u8) {
+        match addr {
+            0xA000..=0xBFFF if addr & 1 == 0 => {
+                let reg = self.reg_select;
+                self.regs[reg as usize] = data & 0xF;
+                if reg == REG_ADDR_LOW {
+                    self.execute();
+                }
+            }
+            0xA000..=0xBFFF => self.reg_select = data & 0xF,
+            _ => warn!("TAMA5: Write ${addr:04X} = ${data:02X}"),
+        }
+    }
+
+    fn tick(&mut self, _ctx: &mut impl Context, dots: u64) {
+        self.rtc.tick(dots);
+    }
+
+    fn sync_rtc(&mut self, now: i64, advance: bool) {
+        let elapsed = self.rtc.host_time.sync(now);
+        if advance {
+            self.rtc.advance(elapsed);
+        }
+    }
+
+    fn internal_ram(&self) -> Option<Vec<u8>> {
+        let mut ret = self.ram.clone();
+        ret.extend(self.rtc.save());
+        Some(ret)
+    }
+}
@@ -0,0 +1,355 @@
+//! IPS, UPS and BPS patches
+
+#[derive(thiserror::Error, Debug)]
+pub enum PatchError {
+    #[error("Unknown patch format")]
+    UnknownFormat,
+    #[error("Patch data is corrupted")]
+    Corrupted,
+    #[error("Source ROM does not match the patch: CRC32 expected {expected:08X}, but actual {actual:08X}")]
+    SourceMismatch { expected: u32, actual: u32 },
+    #[error(
+        "Patched ROM checksum mismatch: CRC32 expected {expected:08X}, but actual {actual:08X}"
+    )]
+    TargetMismatch { expected: u32, actual: u32 },
+    #[error("Patch checksum mismatch: CRC32 expected {expected:08X}, but actual {actual:08X}")]
+    PatchMismatch { expected: u32, actual: u32 },
+}
+
+// Larger than any Game Boy ROM, guards against allocating for broken size fields
+const MAX_TARGET_SIZE: usize = 64 * 1024 * 1024;
+
+/// Applies a patch to `rom`, detecting the format from its magic number
+pub fn apply_patch(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if patch.starts_with(b"PATCH") {
+        apply_ips(rom, patch)
+    } else if patch.starts_with(b"UPS1") {
+        apply_ups(rom, patch)
+    } else if patch.starts_with(b"BPS1") {
+        apply_bps(rom, patch)
+    } else {
+        Err(PatchError::UnknownFormat)
+    }
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0_u32;
+    for &b in data {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB88320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], pos: usize) -> Self {
+        Self { data, pos }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], PatchError> {
+        let ret = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or(PatchError::Corrupted)?;
+        self.pos += len;
+        Ok(ret)
+    }
+
+    fn byte(&mut self) -> Result<u8, PatchError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn be(&mut self, len: usize) -> Result<usize, PatchError> {
+        Ok(self
+            .bytes(len)?
+            .iter()
+            .fold(0, |acc, &b| acc << 8 | b as usize))
+    }
+
+    fn u32_le(&mut self) -> Result<u32, PatchError> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    // Variable length number used by UPS and BPS
+    fn number(&mut self) -> Result<usize, PatchError> {
+        let mut ret = 0_usize;
+        let mut shift = 1_usize;
+        loop {
+            let x = self.byte()?;
+            ret = (x as usize & 0x7F)
+                .checked_mul(shift)
+                .and_then(|v| v.checked_add(ret))
+                .ok_or(PatchError::Corrupted)?;
+            if x & 0x80 != 0 {
+                break Ok(ret);
+            }
+            shift = shift.checked_mul(128).ok_or(PatchError::Corrupted)?;
+            ret = ret.checked_add(shift).ok_or(PatchError::Corrupted)?;
+        }
+    }
+}
+
+fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let mut ret = rom.to_vec();
+    let mut r = Reader::new(patch, 5);
+    loop {
+        let offset = r.be(3)?;
+        if offset == 0x454F46 {
+            // "EOF", optionally followed by the truncated size
+            if let Ok(size) = r.be(3) {
+                ret.truncate(size);
+            }
+            break;
+        }
+        let size = r.be(2)?;
+        let (size, data) = if size == 0 {
+            // Run length encoded record
+            let size = r.be(2)?;
+            (size, vec![r.byte()?; size])
+        } else {
+            (size, r.bytes(size)?.to_vec())
+        };
+        if ret.len() < offset + size {
+            ret.resize(offset + size, 0);
+        }
+        ret[offset..offset + size].copy_from_slice(&data);
+    }
+    Ok(ret)
+}
+
+// Source, target and patch CRC32 at the end of UPS and BPS patches
+fn check_footer(rom: &[u8], patch: &[u8]) -> Result<(u32, usize), PatchError> {
+    if patch.len() < 12 {
+        Err(PatchError::Corrupted)?
+    }
+    let body_len = patch.len() - 12;
+    let mut r = Reader::new(patch, body_len);
+    let source_crc = r.u32_le()?;
+    let target_crc = r.u32_le()?;
+    let patch_crc = r.u32_le()?;
+
+    let actual = crc32(&patch[..patch.len() - 4]);
+    if actual != patch_crc {
+        Err(PatchError::PatchMismatch {
+            expected: patch_crc,
+            actual,
+        })?
+    }
+    let actual = crc32(rom);
+    if actual != source_crc {
+        Err(PatchError::SourceMismatch {
+            expected: source_crc,
+            actual,
+        })?
+    }
+    Ok((target_crc, body_len))
+}
+
+fn target_size(r: &mut Reader) -> Result<usize, PatchError> {
+    let _source_size = r.number()?;
+    let target_size = r.number()?;
+    if target_size > MAX_TARGET_SIZE {
+        Err(PatchError::Corrupted)?
+    }
+    Ok(target_size)
+}
+
+fn check_target(target: &[u8], expected: u32) -> Result<(), PatchError> {
+    let actual = crc32(target);
+    if actual != expected {
+        Err(PatchError::TargetMismatch { expected, actual })?
+    }
+    Ok(())
+}
+
+fn apply_ups(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let (target_crc, body_len) = check_footer(rom, patch)?;
+    let mut r = Reader::new(&patch[..body_len], 4);
+
+    let target_size = target_size(&mut r)?;
+    let mut ret = rom.to_vec();
+    ret.resize(target_size, 0);
+
+    let mut pos = 0_usize;
+    while r.pos < body_len {
+        pos = pos.checked_add(r.number()?).ok_or(PatchError::Corrupted)?;
+        loop {
+            let x = r.byte()?;
+            if let Some(b) = ret.get_mut(pos) {
+                *b ^= x;
+            }
+            pos = pos.checked_add(1).ok_or(PatchError::Corrupted)?;
+            if x == 0 {
+                break;
+            }
+        }
+    }
+
+    check_target(&ret, target_crc)?;
+    Ok(ret)
+}
+
+fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let (target_crc, body_len) = check_footer(rom, patch)?;
+    let mut r = Reader::new(&patch[..body_len], 4);
+
+    let target_size = target_size(&mut r)?;
+    let metadata_size = r.number()?;
+    r.bytes(metadata_size)?;
+
+    let mut ret = Vec::with_capacity(target_size);
+    let mut source_offset = 0_isize;
+    let mut target_offset = 0_isize;
+
+    let relative = |r: &mut Reader, offset: &mut isize| -> Result<usize, PatchError> {
+        let d = r.number()?;
+        let delta = (d >> 1) as isize;
+        *offset = if d & 1 != 0 {
+            offset.checked_sub(delta)
+        } else {
+            offset.checked_add(delta)
+        }
+        .ok_or(PatchError::Corrupted)?;
+        usize::try_from(*offset).map_err(|_| PatchError::Corrupted)
+    };
+
+    while r.pos < body_len {
+        let data = r.number()?;
+        let len = (data >> 2) + 1;
+        if ret.len() + len > target_size {
+            Err(PatchError::Corrupted)?
+        }
+        match data & 3 {
+            // Source read
+            0 => {
+                let start = ret.len();
+                let src = rom.get(start..start + len).ok_or(PatchError::Corrupted)?;
+                ret.extend_from_slice(src);
+            }
+            // Target read
+            1 => ret.extend_from_slice(r.bytes(len)?),
+            // Source copy
+            2 => {
+                let start = relative(&mut r, &mut source_offset)?;
+                let end = start.checked_add(len).ok_or(PatchError::Corrupted)?;
+                let src = rom.get(start..end).ok_or(PatchError::Corrupted)?;
+                ret.extend_from_slice(src);
+                source_offset += len as isize;
+            }
+            // Target copy, may overlap with the bytes being written
+            _ => {
+                let start = relative(&mut r, &mut target_offset)?;
+                let end = start.checked_add(len).ok_or(PatchError::Corrupted)?;
+                for i in start..end {
+                    let b = *ret.get(i).ok_or(PatchError::Corrupted)?;
+                    ret.push(b);
+                }
+                target_offset += len as isize;
+            }
+        }
+    }
+
+    if ret.len() != target_size {
+        Err(PatchError::Corrupted)?
+    }
+    check_target(&ret, target_crc)?;
+    Ok(ret)
+}
+
+#[test]
+fn test_crc32() {
+    assert_eq!(crc32(b"123456789"), 0xCBF43926);
+}
+
+#[test]
+fn test_patches() {
+    let rom: Vec<u8> = (0..=255).collect();
+    let mut expected = rom.clone();
+    expected[0x10] = 0xAA;
+    expected[0x11] = 0xBB;
+    expected.extend([0xCC; 4]);
+
+    let mut ips = b"PATCH".to_vec();
+    ips.extend([0x00, 0x00, 0x10, 0x00, 0x02, 0xAA, 0xBB]);
+    ips.extend([0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x04, 0xCC]);
+    ips.extend(b"EOF");
+    assert_eq!(apply_patch(&rom, &ips).unwrap(), expected);
+
+    let footer = |mut patch: Vec<u8>| {
+        patch.extend(crc32(&rom).to_le_bytes());
+        patch.extend(crc32(&expected).to_le_bytes());
+        patch.extend(crc32(&patch).to_le_bytes());
+        patch
+    };
+
+    // Sizes 256 and 260 are encoded as [0x00, 0x81] and [0x04, 0x81]
+    let mut ups = b"UPS1".to_vec();
+    ups.extend([0x00, 0x81, 0x04, 0x81]);
+    ups.extend([0x90, 0x10 ^ 0xAA, 0x11 ^ 0xBB, 0x00]);
+    ups.extend([0x6D, 0x80, 0xCC, 0xCC, 0xCC, 0xCC, 0x00]);
+    let ups = footer(ups);
+    assert_eq!(apply_patch(&rom, &ups).unwrap(), expected);
+
+    let mut bps = b"BPS1".to_vec();
+    bps.extend([0x00, 0x81, 0x04, 0x81, 0x80]);
+    bps.extend([0xBC]); // Source read 16 bytes
+    bps.extend([0x85, 0xAA, 0xBB]); // Target read 2 bytes
+    bps.extend([0x36, 0x86, 0xA4]); // Source copy 238 bytes from $12
+    bps.extend([0x81, 0xCC]); // Target read 1 byte
+    bps.extend([0x8B, 0x00, 0x83]); // Target copy 3 bytes from $100
+    let bps = footer(bps);
+    assert_eq!(apply_patch(&rom, &bps).unwrap(), expected);
+
+    assert!(matches!(
+        apply_patch(&expected, &bps),
+        Err(PatchError::SourceMismatch { .. })
+    ));
+
+    let number = |mut n: usize| {
+        let mut ret = vec![];
+        loop {
+            let x = (n & 0x7F) as u8;
+            n >>= 7;
+            if n == 0 {
+                ret.push(x | 0x80);
+                break ret;
+            }
+            ret.push(x);
+            n -= 1;
+        }
+    };
+
+    // Offset overflows
+    let mut ups = b"UPS1".to_vec();
+    ups.extend([0x00, 0x81, 0x04, 0x81]);
+    ups.extend(number(usize::MAX - 1));
+    ups.extend([0x01, 0x00]);
+    let ups = footer(ups);
+    assert!(matches!(
+        apply_patch(&rom, &ups),
+        Err(PatchError::Corrupted)
+    ));
+
+    // Target copy beyond the target size
+    let mut bps = b"BPS1".to_vec();
+    bps.extend([0x00, 0x81, 0x04, 0x81, 0x80]);
+    bps.extend([0x81, 0xCC]);
+    bps.extend(number(((1 << 40) << 2) | 3));
+    bps.extend([0x80]);
+    let bps = footer(bps);
+    assert!(matches!(
+        apply_patch(&rom, &bps),
+        Err(PatchError::Corrupted)
+    ));
+}
@@ -110,6 +110,10 @@ impl Ppu {
         self.render_graphics = render_graphics;
     }
 
+    pub fn render_graphics(&self) -> bool {
+        self.render_graphics
+    }
+
     pub fn frame_buffer(&self) -> &FrameBuffer {
         &self.frame_buffer
     }
@@ -198,6 +202,11 @@ impl Ppu {
         self.frame
     }
 
+    /// Current scanline, counted even while the LCD is off
+    pub fn ly(&self) -> u8 {
+        self.ly
+    }
+
     pub fn read(&mut self, ctx: &impl Context, addr: u16) -> u8 {
         let data = match addr & 0xff {
             // LCDC: LCD Control (R/W)
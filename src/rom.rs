@@ -1,9 +1,12 @@
-use log::warn;
+use log::{info, warn};
 use std::fmt::Display;
 
-use crate::util::to_si_bytesize;
+use crate::{
+    licensee::{new_licensee_name, old_licensee_name, USE_NEW_LICENSEE_CODE},
+    util::to_si_bytesize,
+};
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Rom {
     pub title: String,
     pub manufacturer_code: [u8; 4],
@@ -20,9 +23,11 @@ pub struct Rom {
     pub header_checksum_ok: bool,
     pub global_checksum: u16,
     pub global_checksum_ok: bool,
+    pub mbc1_multicart: bool,
     pub data: Vec<u8>,
 }
 
+#[derive(PartialEq, Eq, Clone)]
 pub enum CgbFlag {
     NonCgb,
     SupportCgb,
@@ -45,6 +50,7 @@ impl Display for CgbFlag {
     }
 }
 
+#[derive(Clone)]
 pub enum DestinationCode {
     Japanese,
     NonJapanese,
@@ -85,6 +91,8 @@ pub enum Mbc {
     Mbc5,
     Mbc6,
     Mbc7,
+    PocketCamera,
+    Tama5,
     HuC1,
     HuC3,
 }
@@ -95,12 +103,20 @@ pub enum RomError {
     UnknownCartridgeType(u8),
     #[error("Invalid ROM size: ${0:02X}")]
     InvalidRomSize(u8),
+    #[error("ROM is too short to have a header: {0} bytes")]
+    TooShort(u32),
     #[error("ROM size mismatch: header expected {expected}, but actual size is {actual}")]
     RomSizeMismatch { expected: u32, actual: u32 },
     #[error("Invalid RAM size: ${0:02X}")]
     InvalidRamSize(u8),
     #[error("Invalid destination code: ${0:02X}")]
     InvalidDestinationCode(u8),
+    #[error("ROM size cannot be stored in the header: {0}")]
+    UnencodableRomSize(u64),
+    #[error("RAM size cannot be stored in the header: {0}")]
+    UnencodableRamSize(u64),
+    #[error("Title does not fit before the manufacturer code or the CGB flag: {0}")]
+    TitleTooLong(String),
 }
 
 impl Display for Mbc {
@@ -113,6 +129,8 @@ impl Display for Mbc {
             Mbc::Mbc5 => "MBC5",
             Mbc::Mbc6 => "MBC6",
             Mbc::Mbc7 => "MBC7",
+            Mbc::PocketCamera => "Pocket Camera",
+            Mbc::Tama5 => "TAMA5",
             Mbc::HuC1 => "HuC1",
             Mbc::HuC3 => "HuC3",
         };
@@ -151,14 +169,16 @@ impl CartridgeType {
             0x1C => ret.with_mbc(Mbc5).with_rumble(),
             0x1D => ret.with_mbc(Mbc5).with_rumble().with_ram(),
             0x1E => ret.with_mbc(Mbc5).with_rumble().with_ram().with_battery(),
-            0x20 => ret.with_mbc(Mbc6),
+            0x20 => ret.with_mbc(Mbc6).with_ram().with_battery(),
             0x22 => ret
                 .with_mbc(Mbc7)
                 .with_sensor()
                 .with_rumble()
                 .with_ram()
                 .with_battery(),
-            0xFE => ret.with_mbc(HuC3),
+            0xFC => ret.with_mbc(PocketCamera).with_ram().with_battery(),
+            0xFD => ret.with_mbc(Tama5).with_battery().with_timer(),
+            0xFE => ret.with_mbc(HuC3).with_ram().with_battery().with_timer(),
             0xFF => ret.with_mbc(HuC1).with_ram().with_battery(),
             _ => Err(RomError::UnknownCartridgeType(code))?,
         })
@@ -191,7 +211,12 @@ impl CartridgeType {
     }
 
     pub fn has_internal_ram(&self) -> bool {
-        matches!(&self.mbc, Some(Mbc::Mbc2))
+        match &self.mbc {
+            Some(Mbc::Mbc2 | Mbc::Mbc6 | Mbc::Mbc7 | Mbc::Tama5 | Mbc::HuC3) => true,
+            // RTC state is saved after the external RAM
+            Some(Mbc::Mbc3) => self.has_timer,
+            _ => false,
+        }
     }
 }
 
@@ -214,25 +239,149 @@ impl Display for CartridgeType {
     }
 }
 
+/// Validation result of a ROM image
+#[derive(Clone, Debug)]
+pub struct RomReport {
+    /// Size of the input image
+    pub file_size: usize,
+    /// ROM size declared in the header
+    pub header_rom_size: u64,
+    /// How the image was resized to match the header
+    pub size_fix: Option<SizeFix>,
+    /// Nintendo logo in the header is intact
+    pub logo_ok: bool,
+    pub header_checksum_ok: bool,
+    pub global_checksum_ok: bool,
+    /// Invalid header fields and the values used instead
+    pub header_issues: Vec<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SizeFix {
+    /// Power of two sized underdump, repeated up to the header size
+    Mirrored,
+    /// Underdump padded with $FF
+    Padded,
+    /// Overdump truncated to the header size
+    Trimmed,
+}
+
+fn report_issue(issues: &mut Vec<String>, msg: String) {
+    warn!("{msg}");
+    issues.push(msg);
+}
+
+const NINTENDO_LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+// MBC1M multicarts place each game, with its own header, at a 256KiB boundary
+fn is_multicart(bytes: &[u8]) -> bool {
+    const GAME_SIZE: usize = 256 * 1024;
+    if bytes.len() != 4 * GAME_SIZE {
+        return false;
+    }
+    let logo_count = (0..bytes.len())
+        .step_by(GAME_SIZE)
+        .filter(|&offset| bytes[offset + 0x104..offset + 0x134] == NINTENDO_LOGO)
+        .count();
+    logo_count >= 2
+}
+
+// MMM01 multicarts boot into the menu at the end of the ROM,
+// so the header at the beginning of the image belongs to the first game.
+// The menu must have a valid header there, not just a byte that looks like an MMM01 type.
+fn header_offset(bytes: &[u8]) -> usize {
+    if bytes.len() <= 0x8000 {
+        return 0;
+    }
+    let offset = bytes.len() - 0x8000;
+    let header = &bytes[offset + 0x100..offset + 0x150];
+    if matches!(header[0x47], 0x0B..=0x0D)
+        && header[0x04..0x34] == NINTENDO_LOGO
+        && header[0x4d] == header_checksum(header)
+    {
+        offset
+    } else {
+        0
+    }
+}
+
+fn cgb_flag(code: u8) -> CgbFlag {
+    match code {
+        0x80 => CgbFlag::SupportCgb,
+        0xC0 => CgbFlag::OnlyCgb,
+        _ => CgbFlag::NonCgb,
+    }
+}
+
+fn header_checksum(header: &[u8]) -> u8 {
+    header[0x34..=0x4c]
+        .iter()
+        .fold(0_u8, |sum, &byte| sum.wrapping_sub(byte).wrapping_sub(1))
+}
+
+fn global_checksum(bytes: &[u8], header_offset: usize) -> u16 {
+    let mut sum = 0_u16;
+    for (i, &byte) in bytes.iter().enumerate() {
+        if !(header_offset + 0x14e..=header_offset + 0x14f).contains(&i) {
+            sum = sum.wrapping_add(byte as u16);
+        }
+    }
+    sum
+}
+
+// Non power of two sized ROMs consist of chips of power of two sizes.
+// Addresses beyond the first chip wrap around within the rest of the chips.
+fn mirror_offset(offset: usize, len: usize) -> usize {
+    if len.is_power_of_two() {
+        return offset % len;
+    }
+    let offset = offset % len.next_power_of_two();
+    if offset < len {
+        return offset;
+    }
+    let first = 1 << (usize::BITS - 1 - len.leading_zeros());
+    first + mirror_offset(offset - first, len - first)
+}
+
 impl Rom {
     pub fn from_bytes(bytes: &[u8]) -> Result<Rom, RomError> {
-        let header = &bytes[0x100..=0x14f];
+        Self::load(bytes, false).map(|(rom, _)| rom)
+    }
+
+    /// Loads a ROM image leniently
+    ///
+    /// Images whose size does not match the header are mirrored, padded or trimmed,
+    /// and invalid header fields fall back to usable values.
+    /// Only images without a complete header or with an unknown cartridge type are rejected.
+    pub fn from_bytes_tolerant(bytes: &[u8]) -> Result<(Rom, RomReport), RomError> {
+        Self::load(bytes, true)
+    }
+
+    fn load(bytes: &[u8], tolerant: bool) -> Result<(Rom, RomReport), RomError> {
+        let header_offset = header_offset(bytes);
+
+        if bytes.len() < 0x150 {
+            Err(RomError::TooShort(bytes.len() as u32))?
+        }
+
+        let header = &bytes[header_offset + 0x100..=header_offset + 0x14f];
+        let mut header_issues = vec![];
 
         let title = String::from_utf8_lossy(&header[0x34..=0x43]).to_string();
 
         let manufacturer_code: [u8; 4] = header[0x3f..=0x42].try_into().unwrap();
-        let cgb_flag = match header[0x43] {
-            0x80 => CgbFlag::SupportCgb,
-            0xC0 => CgbFlag::OnlyCgb,
-            _ => CgbFlag::NonCgb,
-        };
+        let cgb_flag = cgb_flag(header[0x43]);
 
         let new_licensee_code: [u8; 2] = header[0x44..=0x45].try_into().unwrap();
         let sgb_flag = match header[0x46] {
             0x03 => true,
             0x00 => false,
             v => {
-                warn!("Invalid SGB flag: ${v:02X}");
+                report_issue(&mut header_issues, format!("Invalid SGB flag: ${v:02X}"));
                 false
             }
         };
@@ -241,35 +390,83 @@ impl Rom {
 
         let rom_size: u64 = match header[0x48] {
             n @ (0x00..=0x08) => (32 * 1024) << n,
+            // Unofficial sizes, listed in some documents but not used by any known cartridge
+            0x52 => 72 * 0x4000,
+            0x53 => 80 * 0x4000,
+            0x54 => 96 * 0x4000,
+            n if tolerant => {
+                let size = (bytes.len() as u64).next_power_of_two().max(0x8000);
+                let msg = format!(
+                    "Invalid ROM size: ${n:02X}. Use image size {}",
+                    to_si_bytesize(size)
+                );
+                report_issue(&mut header_issues, msg);
+                size
+            }
             n => Err(RomError::InvalidRomSize(n))?,
         };
 
-        if bytes.len() as u64 != rom_size {
-            Err(RomError::RomSizeMismatch {
-                expected: rom_size as u32,
-                actual: bytes.len() as u32,
-            })?
-        }
-
         let ram_size = match header[0x49] {
             0 => 0,
             1 => {
-                warn!("Unused RAM size $01. Fallback to 8KB");
+                report_issue(
+                    &mut header_issues,
+                    "Unused RAM size $01. Fallback to 8KB".into(),
+                );
                 8 * 1024
             }
             2 => 8 * 1024,
             3 => 32 * 1024,
             4 => 128 * 1024,
             5 => 64 * 1024,
+            n if tolerant => {
+                let msg = format!("Invalid RAM size: ${n:02X}. Fallback to no RAM");
+                report_issue(&mut header_issues, msg);
+                0
+            }
             n => Err(RomError::InvalidRamSize(n))?,
         };
 
         let destination_code = match header[0x4a] {
             0x00 => DestinationCode::Japanese,
             0x01 => DestinationCode::NonJapanese,
+            v if tolerant => {
+                let msg = format!("Invalid destination code: ${v:02X}. Fallback to Non-Japanese");
+                report_issue(&mut header_issues, msg);
+                DestinationCode::NonJapanese
+            }
             v => Err(RomError::InvalidDestinationCode(v))?,
         };
 
+        let mut data = bytes.to_vec();
+        let size_fix = if data.len() as u64 == rom_size {
+            None
+        } else if !tolerant {
+            Err(RomError::RomSizeMismatch {
+                expected: rom_size as u32,
+                actual: data.len() as u32,
+            })?
+        } else {
+            let len = data.len();
+            let size = rom_size as usize;
+            let fix = if len > size {
+                data.truncate(size);
+                SizeFix::Trimmed
+            } else if len.is_power_of_two() {
+                data = (0..size).map(|i| data[i % len]).collect();
+                SizeFix::Mirrored
+            } else {
+                data.resize(size, 0xFF);
+                SizeFix::Padded
+            };
+            warn!(
+                "ROM size mismatch: header expected {}, but actual size is {}. {fix:?}",
+                to_si_bytesize(rom_size),
+                to_si_bytesize(len as _)
+            );
+            Some(fix)
+        };
+
         // Specifies the games company/publisher code in range $00-FF.
         // A value of $33 signals that the New Licensee Code (in header bytes $0144-0145) is used instead.
         let old_licensee_code = header[0x4b];
@@ -278,10 +475,7 @@ impl Rom {
 
         let header_checksum = header[0x4d];
 
-        let mut header_checksum_calc = 0_u8;
-        for &byte in &header[0x34..=0x4c] {
-            header_checksum_calc = header_checksum_calc.wrapping_sub(byte).wrapping_sub(1);
-        }
+        let header_checksum_calc = self::header_checksum(header);
 
         if header_checksum_calc != header_checksum {
             warn!("Invalid header checksum: checksum in ROM is ${header_checksum:02X}, but calculated checksum is ${header_checksum_calc:02X}");
@@ -289,18 +483,28 @@ impl Rom {
 
         let global_checksum = (header[0x4e] as u16) << 8 | header[0x4f] as u16;
 
-        let mut global_checksum_calc = 0_u16;
-        for (i, &byte) in bytes.iter().enumerate() {
-            if !(0x14e..=0x14f).contains(&i) {
-                global_checksum_calc = global_checksum_calc.wrapping_add(byte as u16);
-            }
-        }
+        let global_checksum_calc = self::global_checksum(&data, header_offset);
 
         if global_checksum_calc != global_checksum {
             warn!("Invalid global checksum: checksum in ROM is ${global_checksum:04X}, but calculated checksum is ${global_checksum_calc:04X}");
         }
 
-        Ok(Rom {
+        let mbc1_multicart = matches!(cartridge_type.mbc, Some(Mbc::Mbc1)) && is_multicart(&data);
+        if mbc1_multicart {
+            info!("MBC1 multicart detected");
+        }
+
+        let report = RomReport {
+            file_size: bytes.len(),
+            header_rom_size: rom_size,
+            size_fix,
+            logo_ok: header[0x04..0x34] == NINTENDO_LOGO,
+            header_checksum_ok: header_checksum_calc == header_checksum,
+            global_checksum_ok: global_checksum_calc == global_checksum,
+            header_issues,
+        };
+
+        let rom = Rom {
             title,
             manufacturer_code,
             cgb_flag,
@@ -316,8 +520,119 @@ impl Rom {
             header_checksum_ok: header_checksum_calc == header_checksum,
             global_checksum,
             global_checksum_ok: global_checksum_calc == global_checksum,
-            data: bytes.to_vec(),
-        })
+            mbc1_multicart,
+            data,
+        };
+
+        Ok((rom, report))
+    }
+
+    /// Serializes the ROM image with the header fields written back and both checksums recomputed
+    ///
+    /// Data is padded with $FF or truncated to `rom_size`.
+    /// The title, manufacturer code and CGB flag share bytes in the header,
+    /// so each of them is written only if it has been changed.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, RomError> {
+        let rom_size_code = match self.rom_size {
+            n if (0x8000..=0x8000 << 8).contains(&n) && n.is_power_of_two() => {
+                (n / 0x8000).trailing_zeros() as u8
+            }
+            n if n == 72 * 0x4000 => 0x52,
+            n if n == 80 * 0x4000 => 0x53,
+            n if n == 96 * 0x4000 => 0x54,
+            n => Err(RomError::UnencodableRomSize(n))?,
+        };
+        let ram_size_code = match self.ram_size {
+            0 => 0,
+            0x2000 => 2,
+            0x8000 => 3,
+            0x20000 => 4,
+            0x10000 => 5,
+            n => Err(RomError::UnencodableRamSize(n))?,
+        };
+
+        let mut data = self.data.clone();
+        data.resize(self.rom_size as usize, 0xFF);
+        let header_offset = header_offset(&data);
+        let header = &mut data[header_offset + 0x100..header_offset + 0x150];
+        let orig = header.to_vec();
+
+        // The manufacturer code and the CGB flag share the last bytes of the title field
+        let has_manufacturer_code = self.manufacturer_code != [0; 4];
+        let has_cgb_flag = self.cgb_flag != CgbFlag::NonCgb;
+
+        let title_changed = String::from_utf8_lossy(&orig[0x34..=0x43]) != self.title;
+        if title_changed {
+            let title = self.title.trim_end_matches('\0').as_bytes();
+            let max_len = if has_manufacturer_code {
+                11
+            } else if has_cgb_flag {
+                15
+            } else {
+                16
+            };
+            if title.len() > max_len {
+                Err(RomError::TitleTooLong(self.title.clone()))?
+            }
+            header[0x34..=0x43].fill(0);
+            header[0x34..0x34 + title.len()].copy_from_slice(title);
+        }
+        if (title_changed && has_manufacturer_code) || orig[0x3f..=0x42] != self.manufacturer_code {
+            header[0x3f..=0x42].copy_from_slice(&self.manufacturer_code);
+        }
+        if (title_changed && has_cgb_flag) || cgb_flag(orig[0x43]) != self.cgb_flag {
+            header[0x43] = match self.cgb_flag {
+                CgbFlag::NonCgb => 0x00,
+                CgbFlag::SupportCgb => 0x80,
+                CgbFlag::OnlyCgb => 0xC0,
+            };
+        }
+
+        header[0x44..=0x45].copy_from_slice(&self.new_licensee_code);
+        header[0x46] = if self.sgb_flag { 0x03 } else { 0x00 };
+        header[0x47] = self.cartridge_type.code;
+        header[0x48] = rom_size_code;
+        header[0x49] = ram_size_code;
+        header[0x4a] = match self.destination_code {
+            DestinationCode::Japanese => 0x00,
+            DestinationCode::NonJapanese => 0x01,
+        };
+        header[0x4b] = self.old_licensee_code;
+        header[0x4c] = self.mask_rom_version;
+        header[0x4d] = header_checksum(header);
+
+        let global_checksum = global_checksum(&data, header_offset);
+        data[header_offset + 0x14e..=header_offset + 0x14f]
+            .copy_from_slice(&global_checksum.to_be_bytes());
+
+        Ok(data)
+    }
+
+    /// Expands ROM data to a power of two size (and at least 32KiB) by mirroring,
+    /// so that mappers can mask bank numbers
+    pub fn mirror_banks(&mut self) {
+        let len = self.data.len();
+        let size = len.next_power_of_two().max(0x8000);
+        if len == 0 || len == size {
+            return;
+        }
+        warn!(
+            "ROM size is not a power of two: {}. Mirror to {}",
+            to_si_bytesize(len as _),
+            to_si_bytesize(size as _)
+        );
+        self.data = (0..size)
+            .map(|i| self.data[mirror_offset(i, len)])
+            .collect();
+    }
+
+    /// Publisher name from the licensee code
+    pub fn publisher(&self) -> Option<&'static str> {
+        if self.old_licensee_code == USE_NEW_LICENSEE_CODE {
+            new_licensee_name(self.new_licensee_code)
+        } else {
+            old_licensee_name(self.old_licensee_code)
+        }
     }
 
     pub fn info(&self) -> Vec<(&str, String)> {
@@ -325,14 +640,24 @@ impl Rom {
             ("Title", self.title.to_owned()),
             ("Manufacturer Code", {
                 let c = self.manufacturer_code;
-                format!("{:02X} {:02X} {:02X} {:02X}", c[0], c[1], c[2], c[3])
+                if c.iter().all(u8::is_ascii_alphanumeric) {
+                    String::from_utf8_lossy(&c).to_string()
+                } else {
+                    format!("{:02X} {:02X} {:02X} {:02X}", c[0], c[1], c[2], c[3])
+                }
             }),
             ("CGB Flag", self.cgb_flag.to_string()),
+            (
+                "Publisher",
+                self.publisher().unwrap_or("Unknown").to_string(),
+            ),
             (
                 "New Licensee Code",
                 format!(
-                    "{:02X} {:02X}",
-                    self.new_licensee_code[0], self.new_licensee_code[1]
+                    "{:02X} {:02X} ({})",
+                    self.new_licensee_code[0],
+                    self.new_licensee_code[1],
+                    new_licensee_name(self.new_licensee_code).unwrap_or("Unknown")
                 ),
             ),
             ("Suport SGB", self.sgb_flag.to_string()),
@@ -340,7 +665,18 @@ impl Rom {
             ("ROM Size", to_si_bytesize(self.rom_size)),
             ("RAM Size", to_si_bytesize(self.ram_size)),
             ("Destination Code", self.destination_code.to_string()),
-            ("Old Licensee Code", self.old_licensee_code.to_string()),
+            (
+                "Old Licensee Code",
+                format!(
+                    "{:02X} ({})",
+                    self.old_licensee_code,
+                    if self.old_licensee_code == USE_NEW_LICENSEE_CODE {
+                        "Use new licensee code"
+                    } else {
+                        old_licensee_name(self.old_licensee_code).unwrap_or("Unknown")
+                    }
+                ),
+            ),
             ("Mask ROM Version", self.mask_rom_version.to_string()),
             (
                 "Header Checksum",
@@ -369,3 +705,102 @@ impl Rom {
         ]
     }
 }
+
+#[test]
+fn test_tolerant_loading() {
+    let mut bytes = vec![0; 0x8000];
+    bytes[0x104..0x134].copy_from_slice(&NINTENDO_LOGO);
+    bytes[0x148] = 0x01; // 64KiB
+    bytes[0x14A] = 0x07; // Invalid destination code
+
+    assert!(matches!(
+        Rom::from_bytes(&bytes[..0x100]),
+        Err(RomError::TooShort(0x100))
+    ));
+    assert!(matches!(
+        Rom::from_bytes(&bytes),
+        Err(RomError::InvalidDestinationCode(7))
+    ));
+
+    let (rom, report) = Rom::from_bytes_tolerant(&bytes).unwrap();
+    assert_eq!(rom.data.len(), 0x10000);
+    assert_eq!(rom.data[0x8104..0x8134], NINTENDO_LOGO);
+    assert_eq!(report.size_fix, Some(SizeFix::Mirrored));
+    assert!(report.logo_ok);
+    assert_eq!(report.header_issues.len(), 1);
+
+    let (rom, report) = Rom::from_bytes_tolerant(&bytes[..0x6000]).unwrap();
+    assert_eq!(rom.data.len(), 0x10000);
+    assert_eq!(rom.data[0x6000], 0xFF);
+    assert_eq!(report.size_fix, Some(SizeFix::Padded));
+
+    bytes.resize(0x10100, 0);
+    let (rom, report) = Rom::from_bytes_tolerant(&bytes).unwrap();
+    assert_eq!(rom.data.len(), 0x10000);
+    assert_eq!(report.size_fix, Some(SizeFix::Trimmed));
+}
+
+#[test]
+fn test_mmm01_header() {
+    let mut bytes = vec![0; 0x20000];
+    bytes[0x104..0x134].copy_from_slice(&NINTENDO_LOGO);
+    bytes[0x147] = 0x01; // MBC1
+    bytes[0x148] = 0x02; // 128KiB
+
+    // Game data that happens to have an MMM01 type at the last header position
+    bytes[0x18147] = 0x0B;
+    let rom = Rom::from_bytes(&bytes).unwrap();
+    assert!(matches!(rom.cartridge_type.mbc, Some(Mbc::Mbc1)));
+
+    let menu = &mut bytes[0x18100..0x18150];
+    menu[0x04..0x34].copy_from_slice(&NINTENDO_LOGO);
+    menu[0x48] = 0x02;
+    menu[0x4d] = header_checksum(menu);
+    let rom = Rom::from_bytes(&bytes).unwrap();
+    assert!(matches!(rom.cartridge_type.mbc, Some(Mbc::Mmm01)));
+}
+
+#[test]
+fn test_to_bytes() {
+    let mut bytes = vec![0; 0x8000];
+    bytes[0x104..0x134].copy_from_slice(&NINTENDO_LOGO);
+    bytes[0x134..0x144].copy_from_slice(b"0123456789ABCDEF");
+
+    let rom = Rom::from_bytes(&bytes).unwrap();
+    assert!(!rom.header_checksum_ok);
+    let fixed = Rom::from_bytes(&rom.to_bytes().unwrap()).unwrap();
+    assert!(fixed.header_checksum_ok && fixed.global_checksum_ok);
+    assert_eq!(fixed.title, "0123456789ABCDEF");
+
+    let mut rom = fixed;
+    rom.title = "HACK".to_string();
+    rom.cgb_flag = CgbFlag::SupportCgb;
+    rom.sgb_flag = true;
+    rom.cartridge_type = CartridgeType::from_code(0x1B).unwrap();
+    rom.rom_size = 0x10000;
+    rom.ram_size = 0x8000;
+    let bytes = rom.to_bytes().unwrap();
+    assert_eq!(bytes.len(), 0x10000);
+
+    let rom = Rom::from_bytes(&bytes).unwrap();
+    assert!(rom.header_checksum_ok && rom.global_checksum_ok);
+    assert!(rom.title.starts_with("HACK\0"));
+    assert!(rom.cgb_flag == CgbFlag::SupportCgb && rom.sgb_flag);
+    assert!(matches!(rom.cartridge_type.mbc, Some(Mbc::Mbc5)));
+    assert_eq!((rom.rom_size, rom.ram_size), (0x10000, 0x8000));
+
+    // Title only change keeps the manufacturer code and the CGB flag
+    let mut bytes = vec![0; 0x8000];
+    bytes[0x104..0x134].copy_from_slice(&NINTENDO_LOGO);
+    bytes[0x134..0x144].copy_from_slice(b"GAME\0\0\0\0\0\0\0AXYE\x80");
+    let mut rom = Rom::from_bytes(&bytes).unwrap();
+    rom.title = "NEW GAME".to_string();
+    let rom = Rom::from_bytes(&rom.to_bytes().unwrap()).unwrap();
+    assert!(rom.title.starts_with("NEW GAME\0"));
+    assert_eq!(&rom.manufacturer_code, b"AXYE");
+    assert!(rom.cgb_flag == CgbFlag::SupportCgb);
+
+    let mut rom = rom;
+    rom.title = "TWELVE CHARS".to_string();
+    assert!(matches!(rom.to_bytes(), Err(RomError::TitleTooLong(_))));
+}
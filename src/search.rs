@@ -0,0 +1,186 @@
+//! Memory search for finding cheat addresses
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Region {
+    /// Work RAM, including all CGB banks
+    Wram,
+    /// High RAM
+    Hram,
+    /// Cartridge RAM
+    Sram,
+}
+
+/// Size of values in little endian
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ValueSize {
+    U8,
+    U16,
+    U24,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Filter {
+    /// Current value equals the given value
+    Equal(u32),
+    /// Value differs from the previous search
+    Changed,
+    /// Value is the same as the previous search
+    Unchanged,
+    /// Value is greater than the previous search
+    Greater,
+    /// Value is less than the previous search
+    Less,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Candidate {
+    pub region: Region,
+    pub bank: u8,
+    pub address: u16,
+    /// Value at the last search
+    pub value: u32,
+    offset: usize,
+}
+
+/// Contents of the searchable memory regions
+#[derive(Clone, Default)]
+pub struct Snapshot {
+    pub wram: Vec<u8>,
+    pub hram: Vec<u8>,
+    pub sram: Vec<u8>,
+}
+
+impl Snapshot {
+    fn region(&self, region: Region) -> &[u8] {
+        match region {
+            Region::Wram => &self.wram,
+            Region::Hram => &self.hram,
+            Region::Sram => &self.sram,
+        }
+    }
+}
+
+pub struct MemorySearch {
+    size: ValueSize,
+    bcd: bool,
+    candidates: Vec<Candidate>,
+}
+
+impl MemorySearch {
+    /// Starts a search with every location holding a valid value as a candidate
+    pub fn new(snapshot: &Snapshot, size: ValueSize, bcd: bool) -> Self {
+        let mut ret = Self {
+            size,
+            bcd,
+            candidates: vec![],
+        };
+        for region in [Region::Wram, Region::Hram, Region::Sram] {
+            for offset in 0..snapshot.region(region).len() {
+                if let Some(value) = ret.value(snapshot, region, offset) {
+                    let (bank, address) = location(region, offset);
+                    ret.candidates.push(Candidate {
+                        region,
+                        bank,
+                        address,
+                        value,
+                        offset,
+                    });
+                }
+            }
+        }
+        ret
+    }
+
+    /// Narrows down the candidates, comparing with the values at the previous search
+    pub fn filter(&mut self, snapshot: &Snapshot, filter: Filter) {
+        let mut candidates = std::mem::take(&mut self.candidates);
+        candidates.retain_mut(|c| {
+            let Some(value) = self.value(snapshot, c.region, c.offset) else {
+                return false;
+            };
+            let prev = c.value;
+            c.value = value;
+            match filter {
+                Filter::Equal(v) => value == v,
+                Filter::Changed => value != prev,
+                Filter::Unchanged => value == prev,
+                Filter::Greater => value > prev,
+                Filter::Less => value < prev,
+            }
+        });
+        self.candidates = candidates;
+    }
+
+    pub fn candidates(&self) -> &[Candidate] {
+        &self.candidates
+    }
+
+    fn value(&self, snapshot: &Snapshot, region: Region, offset: usize) -> Option<u32> {
+        let len = match self.size {
+            ValueSize::U8 => 1,
+            ValueSize::U16 => 2,
+            ValueSize::U24 => 3,
+        };
+        // Values do not cross bank boundaries
+        if location(region, offset).0 != location(region, offset + len - 1).0 {
+            return None;
+        }
+        let bytes = snapshot.region(region).get(offset..offset + len)?;
+
+        let mut ret = 0;
+        for &b in bytes.iter().rev() {
+            ret = if self.bcd {
+                if b >> 4 > 9 || b & 0xF > 9 {
+                    return None;
+                }
+                ret * 100 + (b >> 4) as u32 * 10 + (b & 0xF) as u32
+            } else {
+                ret << 8 | b as u32
+            };
+        }
+        Some(ret)
+    }
+}
+
+// Bank number and CPU address of an offset in the region
+fn location(region: Region, offset: usize) -> (u8, u16) {
+    match region {
+        Region::Wram => {
+            let bank = (offset / 0x1000) as u8;
+            let base = if bank == 0 { 0xC000 } else { 0xD000 };
+            (bank, base + (offset % 0x1000) as u16)
+        }
+        Region::Hram => (0, 0xFF80 + offset as u16),
+        Region::Sram => ((offset / 0x2000) as u8, 0xA000 + (offset % 0x2000) as u16),
+    }
+}
+
+#[test]
+fn test_memory_search() {
+    let mut snapshot = Snapshot {
+        wram: vec![0; 0x8000],
+        hram: vec![0; 0x7F],
+        sram: vec![],
+    };
+    snapshot.wram[0x3010] = 0x99;
+    snapshot.wram[0x3011] = 0x12;
+
+    let mut search = MemorySearch::new(&snapshot, ValueSize::U16, true);
+    search.filter(&snapshot, Filter::Equal(1299));
+    assert_eq!(search.candidates().len(), 1);
+    let c = search.candidates()[0];
+    assert_eq!((c.region, c.bank, c.address), (Region::Wram, 3, 0xD010));
+
+    snapshot.wram[0x3010] = 0x00;
+    snapshot.wram[0x3011] = 0x13;
+    search.filter(&snapshot, Filter::Greater);
+    assert_eq!(search.candidates()[0].value, 1300);
+    search.filter(&snapshot, Filter::Changed);
+    assert!(search.candidates().is_empty());
+
+    // Invalid BCD values are never candidates
+    snapshot.wram[0x0000] = 0x0A;
+    let mut search = MemorySearch::new(&snapshot, ValueSize::U8, true);
+    search.filter(&snapshot, Filter::Unchanged);
+    assert_eq!(search.candidates().len(), 0x8000 + 0x7F - 1);
+}
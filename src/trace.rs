@@ -0,0 +1,154 @@
+//! Per-instruction CPU trace logs that can be diffed against other emulators
+
+use std::io::{self, Write};
+
+use crate::cpu::{disassemble_with, Register};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TraceFormat {
+    /// Gameboy Doctor format
+    ///
+    /// `A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02`
+    Doctor,
+    /// BGB-like format with the disassembly
+    ///
+    /// `ROM0:0100 00       NOP                  AF:01B0 BC:0013 DE:00D8 HL:014D SP:FFFE F:Z-HC IME:0 CY:0`
+    Bgb,
+}
+
+/// CPU state before executing an instruction
+pub struct TraceEntry<'a> {
+    pub reg: &'a Register,
+    /// Address of the instruction
+    pub pc: u16,
+    /// ROM, VRAM or WRAM bank mapped at `pc`
+    pub bank: u16,
+    /// Memory at `pc`, $FF for unreadable addresses
+    pub pcmem: [u8; 4],
+    pub ime: bool,
+    /// Machine cycles since power on
+    pub cycle: u64,
+}
+
+/// Writes a line per executed instruction to `writer`
+pub struct Tracer {
+    writer: Box<dyn Write + Send + Sync>,
+    format: TraceFormat,
+    bank: bool,
+}
+
+impl Tracer {
+    pub fn new(writer: impl Write + Send + Sync + 'static, format: TraceFormat) -> Self {
+        Self {
+            writer: Box::new(writer),
+            format,
+            bank: false,
+        }
+    }
+
+    /// Appends a `BANK:0000` field to the Doctor format, which Gameboy Doctor itself does not have
+    pub fn with_bank(mut self, bank: bool) -> Self {
+        self.bank = bank;
+        self
+    }
+
+    pub fn format(&self) -> TraceFormat {
+        self.format
+    }
+
+    pub fn trace(&mut self, entry: &TraceEntry) -> io::Result<()> {
+        match self.format {
+            TraceFormat::Doctor => self.write_doctor(entry),
+            TraceFormat::Bgb => self.write_bgb(entry),
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    fn write_doctor(&mut self, entry: &TraceEntry) -> io::Result<()> {
+        let reg = entry.reg;
+        let m = &entry.pcmem;
+        write!(
+            self.writer,
+            "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} \
+            SP:{:04X} PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+            reg.a,
+            reg.f.pack(),
+            reg.b,
+            reg.c,
+            reg.d,
+            reg.e,
+            reg.h,
+            reg.l,
+            reg.sp,
+            entry.pc,
+            m[0],
+            m[1],
+            m[2],
+            m[3],
+        )?;
+        if self.bank {
+            write!(self.writer, " BANK:{:04X}", entry.bank)?;
+        }
+        writeln!(self.writer)
+    }
+
+    fn write_bgb(&mut self, entry: &TraceEntry) -> io::Result<()> {
+        let pc = entry.pc;
+        let instr = disassemble_with(pc, |addr| {
+            entry.pcmem.get(addr.wrapping_sub(pc) as usize).copied()
+        });
+        let (bytes, asm) = match &instr {
+            Some(instr) => (
+                instr
+                    .bytes
+                    .iter()
+                    .map(|b| format!("{b:02X}"))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                instr.to_string(),
+            ),
+            None => (format!("{:02X}", entry.pcmem[0]), "???".to_string()),
+        };
+
+        let reg = entry.reg;
+        let f = &reg.f;
+        writeln!(
+            self.writer,
+            "{region}{bank:X}:{pc:04X} {bytes:8} {asm:20} \
+            AF:{a:02X}{f:02X} BC:{b:02X}{c:02X} DE:{d:02X}{e:02X} HL:{h:02X}{l:02X} SP:{sp:04X} \
+            F:{zf}{nf}{hf}{cf} IME:{ime} CY:{cycle}",
+            region = region_name(pc),
+            bank = entry.bank,
+            a = reg.a,
+            f = f.pack(),
+            b = reg.b,
+            c = reg.c,
+            d = reg.d,
+            e = reg.e,
+            h = reg.h,
+            l = reg.l,
+            sp = reg.sp,
+            zf = if f.z { 'Z' } else { '-' },
+            nf = if f.n { 'N' } else { '-' },
+            hf = if f.h { 'H' } else { '-' },
+            cf = if f.c { 'C' } else { '-' },
+            ime = entry.ime as u8,
+            cycle = entry.cycle,
+        )
+    }
+}
+
+fn region_name(addr: u16) -> &'static str {
+    match addr {
+        0x0000..=0x7FFF => "ROM",
+        0x8000..=0x9FFF => "VRA",
+        0xA000..=0xBFFF => "SRA",
+        0xC000..=0xDFFF => "WRA",
+        0xE000..=0xFDFF => "ECH",
+        0xFE00..=0xFF7F => "IO",
+        0xFF80..=0xFFFF => "HRA",
+    }
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use common::{copy, ei, forever, make_rom, reti, run, store};
+
+#[test]
+fn cheat_codes() {
+    let code = [
+        store(0x0000, 0x0A),
+        forever(&[copy(0x4123, 0xA000), copy(0xC000, 0xA001)].concat()),
+    ]
+    .concat();
+    let rom = make_rom(0x03, 0x00, 0x02, &code);
+    let ram = run(&rom, 3, |gb| {
+        gb.add_cheat("3C1-23B-EFA").unwrap();
+        gb.add_cheat("014200C0").unwrap();
+    });
+    assert_eq!(ram[0..2], [0x3C, 0x42]);
+
+    // Compare value does not match the ROM data
+    let ram = run(&rom, 3, |gb| {
+        gb.add_cheat("3C1-23B-F6A").unwrap();
+    });
+    assert_eq!(ram[0], 0x00);
+}
+
+#[test]
+fn game_shark_before_vblank_handler() {
+    // The game keeps clearing $C000 and its VBlank handler saves it to RAM
+    let code = [
+        store(0x0000, 0x0A),
+        store(0xFF40, 0x80),
+        store(0xFFFF, 0x01),
+        ei(),
+        forever(&store(0xC000, 0x00)),
+    ]
+    .concat();
+    let mut rom = make_rom(0x03, 0x00, 0x02, &code);
+    let handler = [copy(0xC000, 0xA000), reti()].concat();
+    rom[0x40..0x40 + handler.len()].copy_from_slice(&handler);
+
+    let ram = run(&rom, 3, |gb| {
+        gb.add_cheat("014200C0").unwrap();
+    });
+    assert_eq!(ram[0], 0x42);
+}
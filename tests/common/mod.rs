@@ -0,0 +1,94 @@
+//! Helpers shared by the integration tests that run small hand-assembled ROMs
+
+#![allow(dead_code)]
+
+use meru_interface::EmulatorCore;
+
+use tgbr::{
+    config::{BootRom, Config, Model},
+    gameboy::GameBoy,
+};
+
+const NINTENDO_LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+/// Builds a ROM image with a valid header that jumps to `code` placed at $0150
+pub fn make_rom(cartridge_type: u8, rom_size: u8, ram_size: u8, code: &[u8]) -> Vec<u8> {
+    let mut rom = vec![0; 0x8000 << rom_size];
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+    rom[0x104..0x134].copy_from_slice(&NINTENDO_LOGO);
+    rom[0x147] = cartridge_type;
+    rom[0x148] = rom_size;
+    rom[0x149] = ram_size;
+    rom[0x14D] = rom[0x134..0x14D]
+        .iter()
+        .fold(0_u8, |sum, &byte| sum.wrapping_sub(byte).wrapping_sub(1));
+    rom[0x150..0x150 + code.len()].copy_from_slice(code);
+    rom
+}
+
+/// ld a,data; ld (addr),a
+pub fn store(addr: u16, data: u8) -> Vec<u8> {
+    let [lo, hi] = addr.to_le_bytes();
+    vec![0x3E, data, 0xEA, lo, hi]
+}
+
+/// ld a,(src); ld (dst),a
+pub fn copy(src: u16, dst: u16) -> Vec<u8> {
+    let [src_lo, src_hi] = src.to_le_bytes();
+    let [dst_lo, dst_hi] = dst.to_le_bytes();
+    vec![0xFA, src_lo, src_hi, 0xEA, dst_lo, dst_hi]
+}
+
+/// call addr
+pub fn call(addr: u16) -> Vec<u8> {
+    let [lo, hi] = addr.to_le_bytes();
+    vec![0xCD, lo, hi]
+}
+
+/// ret
+pub fn ret() -> Vec<u8> {
+    vec![0xC9]
+}
+
+/// ei
+pub fn ei() -> Vec<u8> {
+    vec![0xFB]
+}
+
+/// reti
+pub fn reti() -> Vec<u8> {
+    vec![0xD9]
+}
+
+/// Repeats `body` with a jr back to its start
+pub fn forever(body: &[u8]) -> Vec<u8> {
+    let offset = -(body.len() as i8) - 2;
+    [body, &[0x18, offset as u8]].concat()
+}
+
+/// DMG without the boot ROM, so the code at $0150 runs on the first frame
+pub fn config() -> Config {
+    Config {
+        model: Model::Dmg,
+        boot_rom: BootRom::None,
+        ..Default::default()
+    }
+}
+
+pub fn boot(rom: &[u8]) -> GameBoy {
+    GameBoy::try_from_file(rom, None, &config()).unwrap()
+}
+
+/// Runs `rom` for `frames` frames and returns the backup
+pub fn run(rom: &[u8], frames: usize, setup: impl FnOnce(&mut GameBoy)) -> Vec<u8> {
+    let mut gb = boot(rom);
+    setup(&mut gb);
+    for _ in 0..frames {
+        gb.exec_frame(false);
+    }
+    gb.backup().unwrap()
+}
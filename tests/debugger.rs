@@ -0,0 +1,182 @@
+mod common;
+
+use std::{
+    io::{self, Write},
+    sync::{Arc, Mutex},
+};
+
+use tgbr::{
+    debugger::{Break, Breakpoint, WatchKind, Watchpoint},
+    gameboy::GameBoy,
+    trace::{TraceFormat, Tracer},
+};
+
+use common::{boot, call, copy, ei, forever, make_rom, ret, reti, store};
+
+#[test]
+fn breakpoints_on_banked_rom() {
+    let code = [store(0x2000, 0x02), call(0x4000), forever(&[])].concat();
+    let mut rom = make_rom(0x19, 0x01, 0x00, &code);
+    let sub = [store(0xC000, 0x55), ret()].concat();
+    rom[0x8000..0x8000 + sub.len()].copy_from_slice(&sub);
+
+    let mut gb = boot(&rom);
+    for bank in [1, 2] {
+        gb.debugger_mut().add_breakpoint(Breakpoint {
+            bank: Some(bank),
+            address: 0x4000,
+        });
+    }
+    gb.debugger_mut().add_watchpoint(Watchpoint {
+        address: 0xC000,
+        kind: WatchKind::Write,
+    });
+
+    assert_eq!(
+        gb.run_until_break(false),
+        Some(Break::Breakpoint {
+            bank: 2,
+            address: 0x4000
+        })
+    );
+    assert_eq!(gb.cpu_register().pc, 0x4000);
+    assert_eq!(
+        gb.run_until_break(false),
+        Some(Break::Write {
+            address: 0xC000,
+            data: 0x55
+        })
+    );
+    assert_eq!(gb.run_until_break(false), None);
+}
+
+#[test]
+fn breakpoint_with_pending_interrupt() {
+    // Request a timer interrupt right before the breakpoint
+    let code = [store(0xFFFF, 0x04), ei(), store(0xFF0F, 0x04), forever(&[])].concat();
+    let mut rom = make_rom(0x00, 0x00, 0x00, &code);
+    let handler = [store(0xC000, 0x55), reti()].concat();
+    rom[0x50..0x50 + handler.len()].copy_from_slice(&handler);
+    let pc = 0x150 + code.len() as u16 - 2;
+
+    let mut gb = boot(&rom);
+    for address in [pc, 0x0050] {
+        gb.debugger_mut().add_breakpoint(Breakpoint {
+            bank: None,
+            address,
+        });
+    }
+
+    // The interrupt is dispatched before the instruction at the breakpoint
+    assert!(matches!(
+        gb.run_until_break(false),
+        Some(Break::Breakpoint {
+            address: 0x0050,
+            ..
+        })
+    ));
+    assert_eq!(gb.cpu_register().pc, 0x0050);
+    assert!(matches!(
+        gb.run_until_break(false),
+        Some(Break::Breakpoint { address, .. }) if address == pc
+    ));
+    assert_eq!(gb.memory_snapshot().wram[0], 0x55);
+}
+
+#[test]
+fn step_and_run_until_scanline() {
+    let code = forever(&copy(0xFF44, 0xC000));
+    let rom = make_rom(0x00, 0x00, 0x00, &code);
+    let mut gb = boot(&rom);
+
+    // nop; jp $0150
+    gb.step_instruction();
+    assert_eq!(gb.cpu_register().pc, 0x0101);
+    gb.step_instruction();
+    assert_eq!(gb.cpu_register().pc, 0x0150);
+
+    // ld a,(LY) runs in the first cycle and leaves the rest pending
+    gb.run_cycles(1);
+    assert_eq!(gb.cpu_register().pc, 0x0153);
+    // The other 3 cycles of ld a,(LY) are already counted
+    gb.run_cycles(3);
+    assert_eq!(gb.cpu_register().pc, 0x0153);
+    gb.step_instruction();
+    assert_eq!(gb.cpu_register().pc, 0x0156);
+
+    // Breaks in the middle of ld ($C000),a
+    let watchpoint = Watchpoint {
+        address: 0xC000,
+        kind: WatchKind::Write,
+    };
+    gb.debugger_mut().add_watchpoint(watchpoint);
+    assert!(matches!(
+        gb.run_until_break(false),
+        Some(Break::Write {
+            address: 0xC000,
+            ..
+        })
+    ));
+    assert_eq!(gb.cpu_register().pc, 0x0156);
+    gb.step_instruction();
+    assert_eq!(gb.cpu_register().pc, 0x0150);
+    gb.debugger_mut().remove_watchpoint(&watchpoint);
+
+    let ly = |gb: &GameBoy| gb.memory_snapshot().wram[0];
+    assert_eq!(gb.run_until_vblank(), None);
+    assert_eq!(ly(&gb), 143);
+    gb.run_cycles(20);
+    assert_eq!(ly(&gb), 144);
+
+    gb.run_until_scanline(10);
+    gb.run_cycles(20);
+    assert_eq!(ly(&gb), 10);
+}
+
+#[test]
+fn trace_log() {
+    #[derive(Clone, Default)]
+    struct Sink(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Sink {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let code = store(0xC000, 0x42);
+    let rom = make_rom(0x00, 0x00, 0x00, &code);
+    let mut gb = boot(&rom);
+
+    let doctor = Sink::default();
+    gb.set_tracer(Some(Tracer::new(doctor.clone(), TraceFormat::Doctor)));
+    for _ in 0..3 {
+        gb.step_instruction();
+    }
+    let bgb = Sink::default();
+    gb.set_tracer(Some(Tracer::new(bgb.clone(), TraceFormat::Bgb)));
+    gb.step_instruction();
+    let banked = Sink::default();
+    gb.set_tracer(Some(
+        Tracer::new(banked.clone(), TraceFormat::Doctor).with_bank(true),
+    ));
+    gb.step_instruction();
+
+    let doctor = String::from_utf8(doctor.0.lock().unwrap().clone()).unwrap();
+    let bgb = String::from_utf8(bgb.0.lock().unwrap().clone()).unwrap();
+    let banked = String::from_utf8(banked.0.lock().unwrap().clone()).unwrap();
+    assert_eq!(
+        doctor.lines().collect::<Vec<_>>(),
+        [
+            "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,50,01",
+            "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0101 PCMEM:C3,50,01,CE",
+            "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0150 PCMEM:3E,42,EA,00",
+        ]
+    );
+    assert!(bgb.starts_with("ROM0:0152 EA 00 C0 LD ($C000), A "));
+    assert!(bgb.ends_with("AF:42B0 BC:0013 DE:00D8 HL:014D SP:FFFE F:Z-HC IME:0 CY:7\n"));
+    assert!(banked.ends_with(" PC:0155 PCMEM:00,00,00,00 BANK:0000\n"));
+}
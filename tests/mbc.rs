@@ -0,0 +1,572 @@
+mod common;
+
+use meru_interface::EmulatorCore;
+use std::sync::{
+    atomic::{AtomicI64, Ordering},
+    Arc, Mutex,
+};
+
+use tgbr::{
+    config::{Config, MismatchedBackup},
+    consts::{CAMERA_HEIGHT, CAMERA_WIDTH},
+    gameboy::{Error, GameBoy},
+    interface::{Camera, HostClock, Infrared},
+    io::Input,
+    rom::Mbc,
+    Rom,
+};
+
+use common::{boot, config, copy, forever, make_rom, run, store};
+
+struct HalfBlack;
+
+impl Camera for HalfBlack {
+    fn capture(&mut self, frame: &mut [u8]) {
+        for (i, p) in frame.iter_mut().enumerate() {
+            *p = if i % (CAMERA_WIDTH as usize) < 64 {
+                0
+            } else {
+                255
+            };
+        }
+    }
+}
+
+#[test]
+fn mmm01_menu_maps_game() {
+    // The menu in the last 32KiB selects the game in banks 2-3, enables RAM and locks the mapping
+    let menu = [
+        store(0x6000, 0x04), // ROM bank mask: RA15
+        store(0x2000, 0x02),
+        store(0x0000, 0x4A),
+    ]
+    .concat();
+    let mut rom = make_rom(0x00, 0x00, 0x00, &forever(&[]));
+    rom.resize(0x20000, 0);
+    rom[0x18000..].copy_from_slice(&make_rom(0x0D, 0x02, 0x02, &menu)[..0x8000]);
+
+    // The game continues right after the menu code
+    let game = [copy(0x4000, 0xA000), copy(0x3FFF, 0xA001), forever(&[])].concat();
+    let pc = 0x150 + menu.len();
+    rom[0x8000 + pc..0x8000 + pc + game.len()].copy_from_slice(&game);
+    rom[0xBFFF] = 0x22;
+    rom[0xC000] = 0x33;
+
+    let header = Rom::from_bytes(&rom).unwrap();
+    assert!(matches!(header.cartridge_type.mbc, Some(Mbc::Mmm01)));
+    assert_eq!(header.rom_size, 0x20000);
+
+    let backup = run(&rom, 1, |_| {});
+    assert_eq!(backup[..2], [0x33, 0x22]);
+}
+
+#[test]
+fn mbc6_flash_commands() {
+    let unlock = |cmd| [store(0x5555, 0xAA), store(0x6AAA, 0x55), store(0x5555, cmd)].concat();
+    let code = [
+        store(0x0000, 0x0A),
+        store(0xA000, 0x12),
+        // Enable flash and map flash $4000 and $2000 to $4000 and $6000
+        store(0x0C00, 0x01),
+        store(0x1000, 0x01),
+        store(0x2000, 0x02),
+        store(0x2800, 0x08),
+        store(0x3000, 0x01),
+        store(0x3800, 0x08),
+        // Program the last byte of the page twice, which only clears bits
+        unlock(0xA0),
+        store(0x407F, 0x3C),
+        unlock(0xA0),
+        store(0x407F, 0x0F),
+        copy(0x407F, 0xA001),
+        // Manufacturer and device ID
+        unlock(0x90),
+        copy(0x4000, 0xA002),
+        copy(0x4001, 0xA003),
+        store(0x4000, 0xF0),
+        // Program the first page again, then erase its sector
+        unlock(0xA0),
+        store(0x4000, 0x56),
+        store(0x407F, 0x00),
+        unlock(0x80),
+        unlock(0x30),
+        forever(&[]),
+    ]
+    .concat();
+    let rom = make_rom(0x20, 0x00, 0x03, &code);
+
+    let backup = run(&rom, 1, |_| {});
+    assert_eq!(backup.len(), 0x8000 + 0x100000);
+    assert_eq!(backup[..4], [0x12, 0x0C, 0xC2, 0x81]);
+    let flash = &backup[0x8000..];
+    assert!(flash[..0x20000].iter().all(|&b| b == 0xFF));
+
+    // Backup of SRAM followed by flash
+    let mut save = backup.clone();
+    save[0x8000 + 0x20000] = 0x5A;
+    let gb = GameBoy::try_from_file(&rom, Some(&save), &config()).unwrap();
+    assert_eq!(gb.backup().unwrap(), save);
+}
+
+#[test]
+fn mbc7_eeprom_and_accelerometer() {
+    // Clocks `len` bits into the EEPROM, MSB first
+    let eeprom = |bits: u32, len: usize| -> Vec<u8> {
+        (0..len)
+            .rev()
+            .flat_map(|i| {
+                let di = ((bits >> i) & 1) as u8 * 0x02;
+                [store(0xA080, 0x80 | di), store(0xA080, 0xC0 | di)].concat()
+            })
+            .collect()
+    };
+    let deselect = store(0xA080, 0x00);
+    // ld a,(addr); and mask; cp data; jp nz,$0000
+    let check = |addr: u16, mask: u8, data: u8| -> Vec<u8> {
+        let [lo, hi] = addr.to_le_bytes();
+        vec![0xFA, lo, hi, 0xE6, mask, 0xFE, data, 0xC2, 0x00, 0x00]
+    };
+
+    let mut code = [
+        store(0x0000, 0x0A),
+        store(0x4000, 0x40),
+        // Latch the accelerometer, centered at $81D0 with $70 per gravity
+        store(0xA000, 0x55),
+        store(0xA010, 0xAA),
+        check(0xA020, 0xFF, 0x40),
+        check(0xA030, 0xFF, 0x82),
+        check(0xA040, 0xFF, 0x98),
+        check(0xA050, 0xFF, 0x81),
+        // Start bit, 2-bit opcode and 8-bit address: EWEN, WRITE $03 = $BEEF, READ $03
+        eeprom(0x4C0, 11),
+        deselect.clone(),
+        eeprom(0x503, 11),
+        eeprom(0xBEEF, 16),
+        deselect.clone(),
+        eeprom(0x603, 11),
+        // A dummy zero bit precedes data
+        check(0xA080, 0x01, 0x00),
+    ]
+    .concat();
+    for i in (0..16).rev() {
+        code.extend(eeprom(0, 1));
+        code.extend(check(0xA080, 0x01, ((0xBEEF >> i) & 1) as u8));
+    }
+    code.extend(deselect.clone());
+    // Reached only when every check passed: WRITE $10 = $600D
+    code.extend([eeprom(0x510, 11), eeprom(0x600D, 16), deselect].concat());
+    code.extend(forever(&[]));
+
+    let mut rom = make_rom(0x22, 0x00, 0x00, &code);
+    // Failed checks end up here
+    rom[..2].copy_from_slice(&forever(&[]));
+
+    let backup = run(&rom, 1, |gb| {
+        gb.set_raw_input(&Input {
+            tilt_x: 1.0,
+            tilt_y: -0.5,
+            ..Default::default()
+        })
+    });
+    assert_eq!(backup.len(), 256);
+    assert_eq!(backup[6..8], [0xEF, 0xBE]);
+    assert_eq!(backup[0x20..0x22], [0x0D, 0x60]);
+}
+
+/// Records the LED and sees light all the time
+#[derive(Clone, Default)]
+struct IrRecorder(Arc<Mutex<Vec<bool>>>);
+
+impl Infrared for IrRecorder {
+    fn send(&mut self, led_on: bool) {
+        self.0.lock().unwrap().push(led_on);
+    }
+    fn try_recv(&mut self) -> Option<bool> {
+        Some(true)
+    }
+}
+
+#[test]
+fn huc1_infrared() {
+    let code = [
+        store(0x0000, 0x0E),
+        store(0xA000, 0x01),
+        store(0xA000, 0x01),
+        store(0xA000, 0x00),
+        copy(0xA000, 0xC000),
+        // Back to RAM
+        store(0x0000, 0x00),
+        store(0xA000, 0x77),
+        copy(0xC000, 0xA001),
+        forever(&[]),
+    ]
+    .concat();
+    let rom = make_rom(0xFF, 0x00, 0x02, &code);
+    let ir = IrRecorder::default();
+    let backup = run(&rom, 1, |gb| gb.set_infrared(Some(ir.clone())));
+    assert_eq!(*ir.0.lock().unwrap(), [true, false]);
+    assert_eq!(backup[..2], [0x77, 0xC1]);
+}
+
+#[test]
+fn huc3_commands() {
+    let cmd = |data| store(0xA000, data);
+    let read_result = |dst| [store(0x0000, 0x0C), copy(0xA000, dst), store(0x0000, 0x0B)].concat();
+    let code = [
+        store(0x0000, 0x0B),
+        // Set the time to 186 minutes and 2 days
+        cmd(0x40),
+        cmd(0x50),
+        [0x3A, 0x3B, 0x30, 0x32, 0x30, 0x30, 0x30].map(cmd).concat(),
+        cmd(0x61),
+        // Clear memory, copy the time back and read the minutes
+        cmd(0x40),
+        [0x30; 3].map(cmd).concat(),
+        cmd(0x60),
+        cmd(0x40),
+        cmd(0x10),
+        read_result(0xC000),
+        cmd(0x10),
+        read_result(0xC001),
+        cmd(0x10),
+        read_result(0xC002),
+        // Status and semaphore
+        cmd(0x62),
+        read_result(0xC003),
+        store(0x0000, 0x0D),
+        copy(0xA000, 0xC004),
+        // The speaker is not emulated, playing a tone stays silent
+        store(0x0000, 0x0B),
+        cmd(0x6E),
+        forever(&[]),
+    ]
+    .concat();
+    let rom = make_rom(0xFE, 0x00, 0x02, &code);
+
+    let mut gb = boot(&rom);
+    gb.exec_frame(false);
+    assert_eq!(gb.memory_snapshot().wram[..5], [0xA, 0xB, 0x0, 0x1, 0x1]);
+
+    let samples = &gb.audio_buffer().samples;
+    assert!(samples.iter().all(|s| s.left == 0 && s.right == 0));
+
+    let backup = gb.backup().unwrap();
+    assert_eq!(backup.len(), 0x2000 + 17);
+    assert_eq!(backup[0x2000 + 8..0x2000 + 12], [186, 0, 2, 0]);
+
+    let gb = GameBoy::try_from_file(&rom, Some(&backup), &config()).unwrap();
+    assert_eq!(gb.backup().unwrap()[0x2000 + 8..], backup[0x2000 + 8..]);
+}
+
+#[test]
+fn pocket_camera_capture() {
+    // Dither thresholds $40/$80/$C0 for every matrix entry
+    let matrix = (0..48).map(|i| store(0xA006 + i, [0x40, 0x80, 0xC0][i as usize % 3]));
+    let code = [
+        store(0x0000, 0x0A),
+        store(0x4000, 0x10),
+        matrix.collect::<Vec<_>>().concat(),
+        store(0xA002, 0x10),
+        // Start the capture, which lands in RAM bank 0
+        store(0xA000, 0x01),
+        forever(&[]),
+    ]
+    .concat();
+    let rom = make_rom(0xFC, 0x00, 0x04, &code);
+    let ram = run(&rom, 10, |gb| gb.set_camera(Some(HalfBlack)));
+
+    let tiles_per_row = CAMERA_WIDTH as usize / 8;
+    for tile_y in 0..CAMERA_HEIGHT as usize / 8 {
+        for tile_x in 0..tiles_per_row {
+            let offset = 0x100 + (tile_y * tiles_per_row + tile_x) * 16;
+            let expected = if tile_x < tiles_per_row / 2 {
+                0xFF
+            } else {
+                0x00
+            };
+            assert!(ram[offset..offset + 16].iter().all(|&b| b == expected));
+        }
+    }
+}
+
+#[test]
+fn pocket_camera_ram_enable() {
+    let code = [
+        store(0x0000, 0x0A),
+        store(0xA000, 0x5A),
+        // Disabled RAM reads as $FF
+        store(0x0000, 0x00),
+        copy(0xA000, 0xC000),
+        store(0x0000, 0x0A),
+        copy(0xA000, 0xC001),
+        forever(&[]),
+    ]
+    .concat();
+    let rom = make_rom(0xFC, 0x00, 0x04, &code);
+
+    let mut gb = boot(&rom);
+    gb.exec_frame(false);
+    assert_eq!(gb.memory_snapshot().wram[..2], [0xFF, 0x5A]);
+}
+
+#[test]
+fn tama5_ram_backup() {
+    // Write $AB to RAM address $03 through the register window
+    let mut code = vec![];
+    for (reg, value) in [(0x4, 0xB), (0x5, 0xA), (0x6, 0x0), (0x7, 0x3)] {
+        code.extend(store(0xA001, reg));
+        code.extend(store(0xA000, value));
+    }
+    code.extend(forever(&[])); // jr -2
+
+    let rom = make_rom(0xFD, 0x00, 0x00, &code);
+    let backup = run(&rom, 1, |_| {});
+    assert_eq!(backup[0x03], 0xAB);
+}
+
+#[test]
+fn mbc30_banks() {
+    let code = [
+        store(0x0000, 0x0A),
+        store(0x2000, 0x81),
+        store(0x4000, 0x00),
+        copy(0x4000, 0xA000),
+        store(0x4000, 0x05),
+        copy(0x4000, 0xA001),
+        forever(&[]),
+    ]
+    .concat();
+
+    // MBC30 with 4MiB ROM and 64KiB RAM uses all 8 bits of the ROM bank
+    let mut rom = make_rom(0x13, 0x07, 0x05, &code);
+    rom[0x4000] = 0x01;
+    rom[0x81 * 0x4000] = 0x81;
+    let backup = run(&rom, 1, |_| {});
+    assert_eq!(backup.len(), 0x10000);
+    assert_eq!((backup[0], backup[5 * 0x2000 + 1]), (0x81, 0x81));
+
+    // MBC3 masks the ROM bank to 7 bits and has 4 RAM banks
+    let mut rom = make_rom(0x13, 0x06, 0x03, &code);
+    rom[0x4000] = 0x01;
+    let backup = run(&rom, 1, |_| {});
+    assert_eq!(backup.len(), 0x8000);
+    assert_eq!(backup[0], 0x01);
+    assert!(backup[1..].iter().all(|&b| b == 0));
+}
+
+#[test]
+fn mbc3_rtc_counts_emulated_time() {
+    // Start at 5:59
+    let set_time = [
+        store(0x0000, 0x0A),
+        store(0x4000, 0x08),
+        store(0xA000, 59),
+        store(0x4000, 0x09),
+        store(0xA000, 5),
+    ];
+    // Latch the clock and copy seconds and minutes to RAM repeatedly
+    let copy_time = [
+        store(0x6000, 0x00),
+        store(0x6000, 0x01),
+        store(0x4000, 0x08),
+        copy(0xA000, 0xC000),
+        store(0x4000, 0x09),
+        copy(0xA000, 0xC001),
+        store(0x4000, 0x00),
+        copy(0xC000, 0xA000),
+        copy(0xC001, 0xA001),
+    ];
+    let code = [set_time.concat(), forever(&copy_time.concat())].concat();
+
+    // About 2.5 seconds
+    let rom = make_rom(0x10, 0x00, 0x02, &code);
+    let ram = run(&rom, 150, |_| {});
+    assert_eq!(ram[0..2], [1, 6]);
+}
+
+struct FixedClock(i64);
+
+impl HostClock for FixedClock {
+    fn now(&self) -> i64 {
+        self.0
+    }
+}
+
+#[test]
+fn mbc3_rtc_footer() {
+    const SAVED_AT: i64 = 1_600_000_000;
+
+    let rom = make_rom(0x10, 0x00, 0x02, &forever(&[]));
+    let mut save = vec![0xA5; 0x2000];
+    // Current and latched S, M, H, DL, DH
+    for reg in [0, 0, 1, 2, 0, 0, 0, 1, 2, 0] {
+        save.extend_from_slice(&(reg as u32).to_le_bytes());
+    }
+    save.extend_from_slice(&SAVED_AT.to_le_bytes());
+
+    let config = Config {
+        sync_rtc_with_host: true,
+        ..config()
+    };
+    let gb =
+        GameBoy::with_host_clock(&rom, Some(&save), &config, FixedClock(SAVED_AT + 90)).unwrap();
+    let backup = gb.backup().unwrap();
+
+    assert_eq!(backup.len(), 0x2000 + 48);
+    assert!(backup[..0x2000].iter().all(|&b| b == 0xA5));
+    let reg = |i: usize| u32::from_le_bytes(backup[0x2000 + i * 4..][..4].try_into().unwrap());
+    assert_eq!((0..5).map(reg).collect::<Vec<_>>(), [30, 1, 1, 2, 0]);
+    assert_eq!(backup[0x2000 + 40..], (SAVED_AT + 90).to_le_bytes());
+}
+
+#[derive(Clone, Default)]
+struct MovingClock(Arc<AtomicI64>);
+
+impl HostClock for MovingClock {
+    fn now(&self) -> i64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[test]
+fn mbc3_rtc_save_state_ignores_host_time() {
+    let rom = make_rom(0x10, 0x00, 0x02, &forever(&[]));
+    let config = Config {
+        sync_rtc_with_host: true,
+        ..config()
+    };
+    let clock = MovingClock::default();
+    clock.0.store(1_600_000_000, Ordering::Relaxed);
+    let mut gb = GameBoy::with_host_clock(&rom, None, &config, clock.clone()).unwrap();
+    let state = gb.save_state();
+    let rtc = |gb: &GameBoy| gb.backup().unwrap()[0x2000..0x2000 + 20].to_vec();
+    let before = rtc(&gb);
+
+    clock.0.fetch_add(3661, Ordering::Relaxed);
+    gb.load_state(&state).unwrap();
+    assert_eq!(rtc(&gb), before);
+    gb.reset();
+    assert_eq!(rtc(&gb), before);
+}
+
+#[test]
+fn mismatched_backup() {
+    // MBC1+RAM+BATTERY with 8KiB RAM
+    let rom = make_rom(0x03, 0x00, 0x02, &forever(&[]));
+    let save = vec![0xA5; 0x800];
+    let load = |mismatched_backup| {
+        let config = Config {
+            mismatched_backup,
+            ..config()
+        };
+        GameBoy::try_from_file(&rom, Some(&save), &config)
+    };
+
+    assert!(matches!(
+        load(MismatchedBackup::Error),
+        Err(Error::BackupSizeMismatch {
+            expected: 0x2000,
+            actual: 0x800
+        })
+    ));
+
+    let backup = load(MismatchedBackup::Resize).unwrap().backup().unwrap();
+    assert_eq!(backup.len(), 0x2000);
+    assert!(backup[..0x800].iter().all(|&b| b == 0xA5));
+    assert!(backup[0x800..].iter().all(|&b| b == 0));
+
+    let backup = load(MismatchedBackup::Ignore).unwrap().backup().unwrap();
+    assert!(backup.iter().all(|&b| b == 0));
+
+    // ROM only cartridge
+    let rom = make_rom(0x00, 0x00, 0x00, &forever(&[]));
+    let res = GameBoy::try_from_file(&rom, Some(&save), &config());
+    assert!(matches!(res, Err(Error::BackupNotSupported)));
+
+    // Mapper state following the external RAM, HuC3 with 8KiB RAM and TAMA5
+    for (cartridge_type, ram_size, ram_len, expected) in
+        [(0xFE, 0x02, 0x2000, 17), (0xFD, 0x00, 0, 0x20 + 15)]
+    {
+        let rom = make_rom(cartridge_type, 0x00, ram_size, &forever(&[]));
+        let save = vec![0; ram_len + 4];
+        let res = GameBoy::try_from_file(&rom, Some(&save), &config());
+        assert!(matches!(
+            res,
+            Err(Error::BackupSizeMismatch { expected: e, actual: 4 }) if e == expected
+        ));
+    }
+}
+
+#[test]
+fn reset_keeps_backup() {
+    let code = [store(0x0000, 0x0A), store(0xA000, 0x05), forever(&[])].concat();
+    let config = Config {
+        mismatched_backup: MismatchedBackup::Error,
+        ..config()
+    };
+
+    // MBC2 without battery
+    let rom = make_rom(0x05, 0x00, 0x00, &code);
+    let mut gb = GameBoy::try_from_file(&rom, None, &config).unwrap();
+    gb.exec_frame(false);
+    assert_eq!(gb.backup(), None);
+    gb.reset();
+    assert_eq!(gb.backup(), None);
+
+    // MBC2+BATTERY
+    let rom = make_rom(0x06, 0x00, 0x00, &code);
+    let mut gb = GameBoy::try_from_file(&rom, None, &config).unwrap();
+    gb.exec_frame(false);
+    assert_eq!(gb.backup().unwrap()[0], 0x05);
+    gb.reset();
+    assert_eq!(gb.backup().unwrap()[0], 0x05);
+}
+
+#[test]
+fn mbc5_rumble() {
+    // Motor on, 3 nops, motor off
+    let code = forever(&[store(0x4000, 0x08), vec![0x00; 3], store(0x4000, 0x00)].concat());
+    let rom = make_rom(0x1C, 0x00, 0x00, &code);
+    let mut gb = boot(&rom);
+    gb.exec_frame(false);
+    gb.exec_frame(false);
+
+    // Motor is on for half of the loop
+    let rumble = gb.rumble().unwrap();
+    assert!((rumble.intensity - 0.5).abs() < 0.01);
+
+    let rom = make_rom(0x19, 0x00, 0x00, &code);
+    let mut gb = boot(&rom);
+    gb.exec_frame(false);
+    assert!(gb.rumble().is_none());
+}
+
+#[test]
+fn non_power_of_two_rom() {
+    let code = [
+        store(0x0000, 0x0A),
+        store(0x2000, 100),
+        copy(0x4000, 0xA000),
+        forever(&[]),
+    ]
+    .concat();
+    // MBC5+RAM+BATTERY with 96 banks (1.5MiB)
+    let mut rom = make_rom(0x1B, 0x00, 0x02, &code);
+    rom[0x148] = 0x54;
+    rom.resize(96 * 0x4000, 0);
+    for bank in 1..96 {
+        rom[bank * 0x4000] = bank as u8;
+    }
+
+    // Bank 100 is mirrored to 64 + (100 - 96) within the upper 512KiB chip
+    let ram = run(&rom, 1, |_| {});
+    assert_eq!(ram[0], 68);
+}
+
+#[test]
+fn unsupported_memory_size() {
+    // MBC1+RAM+BATTERY cannot bank both 1MiB ROM and 32KiB RAM
+    let rom = make_rom(0x03, 0x05, 0x03, &forever(&[]));
+    let res = GameBoy::try_from_file(&rom, None, &config());
+    assert!(matches!(res, Err(Error::UnsupportedMemorySize { .. })));
+}
@@ -0,0 +1,24 @@
+mod common;
+
+use meru_interface::EmulatorCore;
+
+use tgbr::{config::Config, gameboy::GameBoy, rom::SizeFix};
+
+use common::{config, forever, make_rom};
+
+#[test]
+fn tolerant_rom_report() {
+    // 32KiB declared, 24KiB dumped
+    let rom = make_rom(0x00, 0x00, 0x00, &forever(&[]));
+    let tolerant = Config {
+        tolerant_rom_loading: true,
+        ..config()
+    };
+    let gb = GameBoy::try_from_file(&rom[..0x6000], None, &tolerant).unwrap();
+    let report = gb.rom_report().unwrap();
+    assert_eq!(report.file_size, 0x6000);
+    assert_eq!(report.size_fix, Some(SizeFix::Padded));
+
+    let gb = GameBoy::try_from_file(&rom, None, &config()).unwrap();
+    assert!(gb.rom_report().is_none());
+}
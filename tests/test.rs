@@ -298,4 +298,10 @@ gen_tester! {
             tma_write_reloading,
         },
     },
+
+    mooneye_emulator_only, "mooneye-test-suite/emulator-only", mooneye_check_fn: {
+        mbc1::{
+            multicart_rom_8Mb,
+        },
+    },
 }